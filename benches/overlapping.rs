@@ -0,0 +1,28 @@
+// How the overlapping matches grow with the haystack, run with
+// `cargo bench --bench overlapping`. For a motif of a fixed length sixteen times the
+// haystack takes about sixteen times as long.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use regex_eng::Regex;
+
+fn time(runs: u32, mut f: impl FnMut()) -> Duration {
+    let started = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    started.elapsed() / runs
+}
+
+fn main() {
+    let re = Regex::new("(ATA|TAT)").unwrap();
+    for n in [4_000, 64_000] {
+        let haystack = "ATATGCATAT".repeat(n / 10);
+        let found = re.find_overlapping_iter(&haystack).count();
+        let took = time(5, || {
+            black_box(re.find_overlapping_iter(black_box(&haystack)).count());
+        });
+        println!("{found} matches in {n} bytes: {took:?}");
+    }
+}
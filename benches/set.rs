@@ -0,0 +1,49 @@
+// A set against the same patterns as separate regexes tried one after the other, run with
+// `cargo bench --bench set`. The set goes over the haystack once for all of them.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use regex_eng::{Regex, RegexSet};
+
+fn time(runs: u32, mut f: impl FnMut()) -> Duration {
+    let started = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    started.elapsed() / runs
+}
+
+fn main() {
+    let patterns: Vec<String> = (0..300)
+        .map(|i| match i % 3 {
+            0 => format!("user{i}=[0-9]+"),
+            1 => format!("(GET|POST) /api/v{i}/[a-z]+"),
+            _ => format!("error {i}: [a-z ]+$"),
+        })
+        .collect();
+    let line = "2024-05-17 12:00:01 host=web-7 user150=4211 GET /api/v100/orders status=200 \
+                latency=35ms bytes=1024 agent=curl referer=none error 200: timed out waiting \
+                for upstream keepalive=yes region=eu-west-1 trace=abcdef0123";
+
+    let set = RegexSet::new(&patterns).unwrap();
+    let regexes: Vec<Regex> = patterns.iter().map(|p| Regex::new(p).unwrap()).collect();
+    let looped: Vec<bool> = regexes.iter().map(|re| re.is_match(line)).collect();
+    let matches = set.matches(line);
+    assert!((0..patterns.len()).all(|i| matches.matched(i) == looped[i]));
+
+    let runs = 20;
+    let as_set = time(runs, || {
+        black_box(set.matches(black_box(line)));
+    });
+    let one_by_one = time(runs, || {
+        for re in &regexes {
+            black_box(re.is_match(black_box(line)));
+        }
+    });
+    println!(
+        "{} patterns on {} bytes: set {as_set:?}, one by one {one_by_one:?}",
+        patterns.len(),
+        line.len()
+    );
+}
@@ -0,0 +1,98 @@
+#!/usr/bin/env perl
+# Writes src/unicode/tables.rs from the Unicode database that ships with perl.
+#
+#   perl scripts/generate-unicode-tables.pl > src/unicode/tables.rs && cargo fmt
+
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_values prop_value_aliases prop_aliases prop_invlist);
+
+my @BINARY = qw(
+    Alphabetic ASCII_Hex_Digit Cased Dash Default_Ignorable_Code_Point Diacritic Emoji
+    Emoji_Component Emoji_Modifier Emoji_Modifier_Base Emoji_Presentation
+    Extended_Pictographic Hex_Digit ID_Continue ID_Start Ideographic Lowercase Math
+    Noncharacter_Code_Point Quotation_Mark Uppercase White_Space XID_Continue XID_Start
+);
+
+# the loose matching from UAX #44, case, spaces, '-' and '_' are ignored
+sub normalize {
+    my $name = lc shift;
+    $name =~ s/[\s_-]//g;
+    return $name;
+}
+
+sub ranges {
+    my @invlist = prop_invlist(shift);
+    push @invlist, 0x110000 if @invlist % 2;
+    my @ranges;
+    while (my ($start, $end) = splice @invlist, 0, 2) {
+        $end -= 1;
+        # surrogates are not scalar values so a `char` can't hold them
+        if ($start < 0xD800 && $end >= 0xD800) {
+            push @ranges, [$start, 0xD7FF];
+            $start = 0xE000;
+        }
+        $start = 0xE000 if $start >= 0xD800 && $start <= 0xDFFF;
+        $end = 0xD7FF if $end >= 0xD800 && $end <= 0xDFFF;
+        push @ranges, [$start, $end] if $start <= $end;
+    }
+    return @ranges;
+}
+
+sub table {
+    my ($const, $ranges) = @_;
+    my @items = map { sprintf "('\\u{%X}', '\\u{%X}')", @$_ } @$ranges;
+    my $out = "const $const: &[(char, char)] = &[";
+    my $line = "";
+    for my $item (@items) {
+        if (length($line) + length($item) + 2 > 92) {
+            $out .= "\n   $line";
+            $line = "";
+        }
+        $line .= " $item,";
+    }
+    $out .= "\n   $line" if $line;
+    $out .= "\n" if @items;
+    return "$out];\n\n";
+}
+
+sub kind {
+    my ($kind, $prefix, $entries) = @_;
+    my $out = "";
+    my (@names, %aliases);
+
+    for my $entry (sort { $a->{name} cmp $b->{name} } @$entries) {
+        my $const = uc "${prefix}_$entry->{name}";
+        $out .= table($const, [ranges($entry->{query})]);
+        push @names, "    (\"$entry->{name}\", $const),\n";
+        $aliases{normalize($_)} = $entry->{name} for @{ $entry->{aliases} };
+    }
+
+    $out .= "pub(crate) const $kind: &[(&str, &[(char, char)])] = &[\n" . join("", @names) . "];\n\n";
+    $out .= "pub(crate) const ${kind}_ALIASES: &[(&str, &str)] = &[\n";
+    $out .= "    (\"$_\", \"$aliases{$_}\"),\n" for sort keys %aliases;
+    return "$out];\n\n";
+}
+
+my @general_category = map {
+    { name => $_, query => "gc=$_", aliases => [prop_value_aliases("gc", $_)] }
+} prop_values("gc");
+
+# Katakana_Or_Hiragana is not used by any code point anymore and has no aliases
+my @script = map {
+    my @aliases = prop_value_aliases("sc", $_);
+    @aliases ? { name => $aliases[1], query => "sc=$aliases[1]", aliases => \@aliases } : ()
+} prop_values("sc");
+
+my @binary = map {
+    { name => $_, query => $_, aliases => [prop_aliases($_)] }
+} @BINARY;
+
+my $version = Unicode::UCD::UnicodeVersion();
+print "// DO NOT EDIT: generated by scripts/generate-unicode-tables.pl\n";
+print "// Unicode $version\n\n";
+print kind("GENERAL_CATEGORY", "gc", \@general_category);
+print kind("SCRIPT", "sc", \@script);
+my $binary = kind("BINARY_PROPERTY", "bp", \@binary);
+$binary =~ s/\n\n\z/\n/;
+print $binary;
@@ -0,0 +1,296 @@
+// A set of characters kept as sorted ranges that never overlap or touch, so two classes
+// with the same characters are always equal. Every engine matches bracket expressions,
+// '.' and \p{..} through this.
+
+use crate::parser::{
+    self, Anchor, Error, ParsingError, PredefinedSet, Range, Repetition, Sets, SubPattern,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    // the ranges are inclusive and can come in any order
+    pub fn new<I: IntoIterator<Item = (char, char)>>(ranges: I) -> Self {
+        let mut ranges: Vec<(char, char)> = ranges
+            .into_iter()
+            .map(|(a, b)| if a <= b { (a, b) } else { (b, a) })
+            .collect();
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some((_, last_end)) if start <= next(*last_end).unwrap_or(char::MAX) => {
+                    *last_end = (*last_end).max(end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+        CharClass { ranges: merged }
+    }
+
+    // a single bracket expression like `[^a-z[:digit:]]`
+    pub fn from_bracket(expression: &str) -> Result<Self, Error> {
+        let malformed = Error {
+            kind: ParsingError::MalformedExpression,
+            position: 0,
+        };
+        match parser::process(expression)? {
+            (Anchor::None, patterns) => match patterns.as_slice() {
+                [pattern] if pattern.repetition == Repetition::None => match &pattern.sub_pattern {
+                    SubPattern::BracketedSet(sets) => Ok(CharClass::from_sets(sets)),
+                    SubPattern::InvertedSet(sets) => Ok(CharClass::from_sets(sets).negate()),
+                    _ => Err(malformed),
+                },
+                _ => Err(malformed),
+            },
+            _ => Err(malformed),
+        }
+    }
+
+    pub(crate) fn from_sets(sets: &[Sets]) -> Self {
+        let mut class = CharClass::default();
+        for set in sets {
+            let other = match set {
+                Sets::PredefinedSets(set) => CharClass::from_predefined(*set),
+                Sets::CustomRange(Range(lower, upper)) => CharClass::new([(*lower, *upper)]),
+                Sets::Custom(chars) => CharClass::new(chars.iter().map(|&c| (c, c))),
+                Sets::Property(property) => CharClass::new(property.ranges.iter().copied()),
+                Sets::NegatedProperty(property) => {
+                    CharClass::new(property.ranges.iter().copied()).negate()
+                }
+                Sets::Class(class) => class.clone(),
+            };
+            class = class.union(&other);
+        }
+        class
+    }
+
+    // the POSIX classes are all ASCII
+    fn from_predefined(set: PredefinedSet) -> Self {
+        CharClass::new(('\0'..='\x7f').filter(|&c| set.contains(c)).map(|c| (c, c)))
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    // the number of characters, not of ranges
+    pub fn len(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|&(start, end)| {
+                let surrogates = if start < '\u{e000}' && end > '\u{d7ff}' {
+                    0x800
+                } else {
+                    0
+                };
+                (end as u32 - start as u32 + 1 - surrogates) as usize
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(start, end)| {
+                if end < c {
+                    std::cmp::Ordering::Less
+                } else if start > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn union(&self, other: &CharClass) -> CharClass {
+        CharClass::new(self.ranges.iter().chain(other.ranges.iter()).copied())
+    }
+
+    pub fn intersect(&self, other: &CharClass) -> CharClass {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a_start, a_end) = self.ranges[i];
+            let (b_start, b_end) = other.ranges[j];
+            let start = a_start.max(b_start);
+            let end = a_end.min(b_end);
+            if start <= end {
+                ranges.push((start, end));
+            }
+            // the range that ends first can't overlap anything else
+            if a_end < b_end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        CharClass { ranges }
+    }
+
+    pub fn negate(&self) -> CharClass {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut start = Some('\0');
+
+        for &(lower, upper) in &self.ranges {
+            if let Some(start) = start.filter(|&start| start < lower) {
+                ranges.push((start, previous(lower).unwrap()));
+            }
+            start = next(upper);
+        }
+        if let Some(start) = start {
+            ranges.push((start, char::MAX));
+        }
+        CharClass { ranges }
+    }
+
+    // adds every other case of the characters, in ASCII mode only the ASCII letters count
+    pub(crate) fn case_fold(&self, unicode: bool) -> CharClass {
+        let mut ranges = self.ranges.clone();
+        for &(start, end) in &self.ranges {
+            if !unicode {
+                let chars = start..=end.min('\x7f');
+                ranges.extend(chars.filter(char::is_ascii_alphabetic).map(|c| {
+                    let other = (c as u8 ^ 0x20) as char;
+                    (other, other)
+                }));
+                continue;
+            }
+            let folds = case_folds();
+            for c in (start..=end).filter(|c| c.is_alphabetic()) {
+                let key = fold_key(c);
+                let first = folds.partition_point(|&(k, _)| k < key);
+                ranges.extend(
+                    folds[first..]
+                        .iter()
+                        .take_while(|&&(k, _)| k == key)
+                        .map(|&(_, c)| (c, c)),
+                );
+            }
+        }
+        CharClass::new(ranges)
+    }
+
+    pub fn difference(&self, other: &CharClass) -> CharClass {
+        self.intersect(&other.negate())
+    }
+
+    pub fn symmetric_difference(&self, other: &CharClass) -> CharClass {
+        self.difference(other).union(&other.difference(self))
+    }
+}
+
+// Characters that are the same ignoring case have the same key, the lowercase of their
+// uppercase. Only the mappings to a single character are used.
+fn fold_key(c: char) -> char {
+    fn single(mut mapping: impl ExactSizeIterator<Item = char>) -> Option<char> {
+        if mapping.len() == 1 {
+            mapping.next()
+        } else {
+            None
+        }
+    }
+    let upper = single(c.to_uppercase()).unwrap_or(c);
+    single(upper.to_lowercase()).unwrap_or(upper)
+}
+
+// every cased character with its key, sorted by the key so the ones that fold together
+// are next to each other
+fn case_folds() -> &'static [(char, char)] {
+    static FOLDS: std::sync::OnceLock<Vec<(char, char)>> = std::sync::OnceLock::new();
+    FOLDS.get_or_init(|| {
+        let mut folds: Vec<(char, char)> = ('\0'..=char::MAX)
+            .filter(|c| c.is_alphabetic())
+            .map(|c| (fold_key(c), c))
+            .filter(|&(key, c)| key != c || c.is_lowercase())
+            .collect();
+        folds.sort_unstable();
+        folds
+    })
+}
+
+// the surrogates are not chars so they are skipped over
+fn next(c: char) -> Option<char> {
+    match c {
+        '\u{d7ff}' => Some('\u{e000}'),
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+fn previous(c: char) -> Option<char> {
+    match c {
+        '\u{e000}' => Some('\u{d7ff}'),
+        '\0' => None,
+        c => char::from_u32(c as u32 - 1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalizing() {
+        let class = CharClass::new([('x', 'z'), ('a', 'c'), ('b', 'f'), ('g', 'g'), ('z', 'a')]);
+        assert_eq!(class.ranges, vec![('a', 'z')]);
+    }
+
+    #[test]
+    fn operations() {
+        let letters = CharClass::new([('a', 'z')]);
+        let vowels = CharClass::new("aeiou".chars().map(|c| (c, c)));
+
+        let consonants = letters.difference(&vowels);
+        assert!(consonants.contains('b') && !consonants.contains('e'));
+        assert_eq!(consonants.intersect(&vowels), CharClass::default());
+        assert_eq!(consonants.union(&vowels), letters);
+        assert_eq!(
+            letters.symmetric_difference(&CharClass::new([('x', '}')])),
+            CharClass::new([('a', 'w'), ('{', '}')])
+        );
+    }
+
+    #[test]
+    fn negating() {
+        let class = CharClass::new([('\0', 'a'), ('\u{d000}', '\u{d7ff}')]);
+        assert_eq!(
+            class.negate().ranges,
+            vec![('b', '\u{cfff}'), ('\u{e000}', char::MAX)]
+        );
+        assert_eq!(class.negate().negate(), class);
+        assert_eq!(CharClass::default().negate().negate(), CharClass::default());
+        assert_eq!(CharClass::default().negate().len(), 0x110000 - 0x800);
+    }
+
+    #[test]
+    fn from_bracket_expressions() {
+        let class = CharClass::from_bracket("[a-cb[:digit:]x]").unwrap();
+        assert_eq!(class.ranges(), &[('0', '9'), ('a', 'c'), ('x', 'x')]);
+        assert_eq!(class.len(), 14);
+        assert!(CharClass::from_bracket("[^a]").unwrap().contains('\n'));
+        assert!(CharClass::from_bracket("[a]+").is_err());
+        assert!(CharClass::from_bracket("a").is_err());
+    }
+
+    #[test]
+    fn case_folding() {
+        let class = CharClass::new([('a', 'c'), ('k', 'k'), ('é', 'é')]);
+        assert_eq!(
+            class.case_fold(false),
+            CharClass::new([('A', 'C'), ('K', 'K'), ('a', 'c'), ('k', 'k'), ('é', 'é')])
+        );
+        let folded = class.case_fold(true);
+        assert!(folded.contains('É') && folded.contains('\u{212a}'));
+        assert!(CharClass::new([('σ', 'σ')]).case_fold(true).contains('ς'));
+        assert_eq!(CharClass::new([('1', '1')]).case_fold(true).len(), 1);
+    }
+}
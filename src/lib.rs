@@ -1,3 +1,14 @@
 // A small minimal regular expression library completely
+mod class;
 mod matcher;
 pub mod parser;
+mod unicode;
+
+pub use class::CharClass;
+pub use matcher::bytes;
+pub use matcher::{
+    CaptureMatches, Captures, Cursor, Error, Haystack, LineTerminator, Match, Matches,
+    OverlappingMatches, RMatches, Regex, RegexBuilder, RegexSet, Replacer, RevCursor, Semantics,
+    SetMatches, SetMatchesIter, Split, SplitCaptures, SplitN, StreamMatch, StreamMatches, Text,
+};
+pub use parser::{escape, escape_for, Syntax};
@@ -0,0 +1,268 @@
+// Searching `&[u8]` haystacks that don't have to be valid UTF-8, like log files or binary
+// dumps. Without unicode '.' and the classes match single bytes and `\xHH` is that byte, with
+// it they match UTF-8 encoded chars just like `Regex` does and skip over invalid bytes.
+// Literal characters in the pattern always match their UTF-8 encoding.
+
+use std::sync::OnceLock;
+
+use super::compile::{self, Program};
+use super::overlapping::Overlaps;
+use super::pikevm::SearchKind;
+use super::reverse::{self, Backward};
+use super::{search, Config, Error, RegexBuilder};
+use crate::parser::{Expression, Options};
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    pub(super) program: Program,
+    pub(super) reverse: OnceLock<Program>,
+    pub(super) options: Options,
+    pub(super) config: Config,
+}
+
+// the same types as for `Regex`, only over bytes
+pub type Match<'h> = super::Match<'h, [u8]>;
+pub type Captures<'h> = super::Captures<'h, [u8]>;
+pub type CaptureMatches<'r, 'h> = super::CaptureMatches<'r, 'h, [u8]>;
+pub type Matches<'r, 'h> = super::Matches<'r, 'h, [u8]>;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        RegexBuilder::new(pattern).build_bytes()
+    }
+
+    pub(super) fn from_expression(
+        pattern: &str,
+        expression: &Expression,
+        options: Options,
+        config: Config,
+    ) -> Result<Regex, Error> {
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program: compile::compile(expression, &config)?,
+            reverse: OnceLock::new(),
+            options,
+            config,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn captures_len(&self) -> usize {
+        self.program.slots / 2
+    }
+
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        self.program.names.iter().map(|name| name.as_deref())
+    }
+
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        self.is_match_at(haystack, 0)
+    }
+
+    pub fn find<'h>(&self, haystack: &'h [u8]) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    pub fn is_match_at(&self, haystack: &[u8], start: usize) -> bool {
+        self.find_at(haystack, start).is_some()
+    }
+
+    pub fn find_at<'h>(&self, haystack: &'h [u8], start: usize) -> Option<Match<'h>> {
+        self.captures_at(haystack, start)
+            .and_then(|captures| captures.get(0))
+    }
+
+    pub fn captures<'h>(&self, haystack: &'h [u8]) -> Option<Captures<'h>> {
+        self.captures_at(haystack, 0)
+    }
+
+    pub fn captures_at<'h>(&self, haystack: &'h [u8], start: usize) -> Option<Captures<'h>> {
+        search(
+            &self.program,
+            self.config,
+            haystack,
+            start,
+            self.config.search_kind(),
+        )
+    }
+
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> Matches<'r, 'h> {
+        super::Matches(self.captures_iter(haystack))
+    }
+
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> CaptureMatches<'r, 'h> {
+        CaptureMatches::new(&self.program, self.config, haystack)
+    }
+
+    pub fn is_full_match(&self, haystack: &[u8]) -> bool {
+        self.full_match(haystack).is_some()
+    }
+
+    pub fn full_match<'h>(&self, haystack: &'h [u8]) -> Option<Captures<'h>> {
+        search(&self.program, self.config, haystack, 0, SearchKind::Full)
+    }
+
+    pub fn match_prefix<'h>(&self, haystack: &'h [u8]) -> Option<Captures<'h>> {
+        search(&self.program, self.config, haystack, 0, SearchKind::Prefix)
+    }
+}
+
+pub struct RMatches<'r, 'h> {
+    pub(super) regex: &'r Regex,
+    pub(super) haystack: &'h [u8],
+    pub(super) back: Backward,
+}
+
+impl<'h> Iterator for RMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        let (regex, haystack) = (self.regex, self.haystack);
+        let reverse = regex.reversed();
+        let found = self.back.next(
+            |end| reverse::rfind(&regex.program, reverse, regex.config, haystack, end),
+            |end| end - 1,
+        )?;
+        Some(Match {
+            haystack,
+            start: found.start,
+            end: found.end,
+        })
+    }
+}
+
+pub struct OverlappingMatches<'r, 'h> {
+    pub(super) regex: &'r Regex,
+    pub(super) haystack: &'h [u8],
+    pub(super) overlaps: Overlaps,
+}
+
+impl OverlappingMatches<'_, '_> {
+    pub fn every_end(mut self, yes: bool) -> Self {
+        self.overlaps.every_end = yes;
+        self
+    }
+}
+
+impl<'h> Iterator for OverlappingMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        let haystack = self.haystack;
+        let found = self.overlaps.next(&self.regex.program, haystack)?;
+        Some(Match {
+            haystack,
+            start: found.start,
+            end: found.end,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::ParsingError;
+
+    fn bytes(pattern: &str, unicode: bool) -> Regex {
+        RegexBuilder::new(pattern)
+            .unicode(unicode)
+            .build_bytes()
+            .unwrap()
+    }
+
+    #[test]
+    fn invalid_utf8_haystacks() {
+        let haystack = b"GET /\xff\xfe?id=42 HTTP\xc3";
+        let re = Regex::new("id=([0-9]+)").unwrap();
+        let caps = re.captures(haystack).unwrap();
+        assert_eq!(&caps[1], b"42");
+        assert_eq!(caps.get(0).unwrap().range(), 8..13);
+
+        // without unicode '.' and negated classes take any single byte
+        assert_eq!(Regex::new("/..").unwrap().find(haystack).unwrap().len(), 3);
+        assert_eq!(
+            Regex::new("[^ ]+$")
+                .unwrap()
+                .find(haystack)
+                .unwrap()
+                .as_bytes(),
+            b"HTTP\xc3"
+        );
+        let found: Vec<_> = Regex::new(".")
+            .unwrap()
+            .find_iter(b"a\n\x80")
+            .map(|m| m.range())
+            .collect();
+        assert_eq!(found, [0..1, 2..3]);
+
+        // with it they only match whole UTF-8 encoded chars
+        let re = bytes("/..", true);
+        assert!(re.find(haystack).is_none());
+        assert_eq!(re.find("/é€".as_bytes()).unwrap().len(), 6);
+        assert_eq!(
+            bytes("[^a]", true).find(b"\xffa\xc3\xa9").unwrap().range(),
+            2..4
+        );
+    }
+
+    #[test]
+    fn byte_escapes() {
+        let re = Regex::new(r"\x00\xff+[\x80-\xbf]").unwrap();
+        assert_eq!(re.find(b"a\x00\xff\xff\x90b").unwrap().range(), 1..5);
+        assert!(!re.is_match("\0ÿ\u{80}".as_bytes()));
+
+        // literal characters are still their UTF-8 encoding and so is `\xHH` with unicode
+        assert_eq!(
+            Regex::new("é")
+                .unwrap()
+                .find("café".as_bytes())
+                .unwrap()
+                .range(),
+            3..5
+        );
+        assert!(bytes(r"\xe9", true).is_match("é".as_bytes()));
+        assert!(!bytes(r"\xe9", true).is_match(b"\xe9"));
+        assert!(Regex::new(r"(?i)\xe9").unwrap().is_match(b"\xe9"));
+
+        let error = |pattern| match Regex::new(pattern).unwrap_err() {
+            Error::Parse(error) => error.kind,
+            error => panic!("{error}"),
+        };
+        assert_eq!(error("[é]"), ParsingError::NonAsciiInByteClass);
+        assert_eq!(error(r"\p{Greek}"), ParsingError::NonAsciiInByteClass);
+        assert_eq!(error(r"[\x{100}]"), ParsingError::NonAsciiInByteClass);
+        assert!(bytes("[é]", true).is_match("é".as_bytes()));
+    }
+
+    #[test]
+    fn empty_matches_between_bytes() {
+        let found: Vec<_> = Regex::new("x*")
+            .unwrap()
+            .find_iter("é".as_bytes())
+            .map(|m| m.range())
+            .collect();
+        assert_eq!(found, [0..0, 1..1, 2..2]);
+        assert!(Regex::new("^$").unwrap().is_match(b""));
+    }
+
+    #[test]
+    fn full_and_prefix_matches() {
+        let re = Regex::new(r"[0-9]+\xff?").unwrap();
+        assert!(re.is_full_match(b"42\xff"));
+        assert!(!re.is_full_match(b"42\xff "));
+        assert_eq!(&re.full_match(b"7").unwrap()[0], b"7");
+        assert_eq!(
+            re.match_prefix(b"12\xffab")
+                .unwrap()
+                .get(0)
+                .unwrap()
+                .range(),
+            0..3
+        );
+        assert!(re.match_prefix(b"a12").is_none());
+    }
+}
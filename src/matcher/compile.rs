@@ -0,0 +1,457 @@
+// Turns the ast from the parser into a program for a thompson style NFA, every engine in
+// `matcher` walks over the same program. The program steps over bytes, a char is the bytes
+// of its UTF-8 encoding and a class is an alternation of them like in RE2.
+
+use std::sync::Arc;
+
+use super::{Config, Error, LineTerminator};
+use crate::class::CharClass;
+use crate::parser::{Anchor, Expression, Pattern, Repetition, SubPattern};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Inst {
+    // one byte in the range
+    Byte(u8, u8),
+    // what an empty class compiles to, no byte gets through
+    Fail,
+    // try the first branch before the second one
+    Split(usize, usize),
+    Jump(usize),
+    // record the current position in the capture slot
+    Save(usize),
+    Assert(Look),
+    // which of the patterns matched, it is always 0 unless the program is for a set
+    Match(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Look {
+    Start,
+    End,
+    // in multi line mode '^' and '$' match next to a line terminator as well
+    StartLine(LineTerminator),
+    EndLine(LineTerminator),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Program {
+    pub(crate) insts: Vec<Inst>,
+    // two slots for every group, the whole match is group 0
+    pub(crate) slots: usize,
+    // the name of every group that has one, indexed by the group
+    pub(crate) names: Arc<[Option<String>]>,
+    // matches only start on char boundaries, false when the haystack is `&[u8]`
+    pub(crate) utf8: bool,
+}
+
+impl Inst {
+    pub(crate) fn matches(&self, byte: u8) -> bool {
+        match self {
+            Inst::Byte(start, end) => (*start..=*end).contains(&byte),
+            _ => false,
+        }
+    }
+}
+
+pub(crate) fn compile(expression: &Expression, config: &Config) -> Result<Program, Error> {
+    compile_set(std::slice::from_ref(expression), config)
+}
+
+// Every expression gets its own copy of the groups, they are tried in order like the
+// branches of an alternation
+pub(crate) fn compile_set(expressions: &[Expression], config: &Config) -> Result<Program, Error> {
+    build(expressions, config, false)
+}
+
+// The program matches the bytes of the expression from the last one back to the first, a
+// thread starts at the end of a match and reaches `Match` at its start. The assertions stay
+// the same since they look at the haystack around a position and not at the direction.
+pub(crate) fn compile_reverse(expression: &Expression, config: &Config) -> Result<Program, Error> {
+    build(std::slice::from_ref(expression), config, true)
+}
+
+fn build(expressions: &[Expression], config: &Config, reverse: bool) -> Result<Program, Error> {
+    let line_terminator = config.line_terminator;
+    let mut compiler = Compiler {
+        insts: Vec::new(),
+        next_group: 1,
+        line_terminator: CharClass::new(line_terminator.chars().iter().map(|&c| (c, c))),
+        size: 0,
+        size_limit: config.size_limit,
+        names: vec![None],
+        byte_classes: config.byte_classes,
+        reverse,
+    };
+    let (mut start, mut end) = if config.multi_line {
+        (
+            Look::StartLine(line_terminator),
+            Look::EndLine(line_terminator),
+        )
+    } else {
+        (Look::Start, Look::End)
+    };
+    let (mut first, mut last) = (Anchor::Start, Anchor::End);
+    if reverse {
+        std::mem::swap(&mut start, &mut end);
+        std::mem::swap(&mut first, &mut last);
+    }
+
+    let mut slots = 2;
+    for (id, (anchor, patterns)) in expressions.iter().enumerate() {
+        let split = if id + 1 < expressions.len() {
+            let next = compiler.insts.len() + 1;
+            Some(compiler.push(Inst::Split(next, 0)))
+        } else {
+            None
+        };
+        compiler.next_group = 1;
+
+        let (open, close) = compiler.slots(0);
+        compiler.push(Inst::Save(open));
+        if *anchor == first || *anchor == Anchor::Both {
+            compiler.push(Inst::Assert(start));
+        }
+        compiler.sequence(patterns);
+        if *anchor == last || *anchor == Anchor::Both {
+            compiler.push(Inst::Assert(end));
+        }
+        compiler.push(Inst::Save(close));
+        compiler.push(Inst::Match(id));
+
+        slots = slots.max(compiler.next_group * 2);
+        if let Some(split) = split {
+            let next = compiler.insts.len();
+            compiler.patch(split, next);
+        }
+    }
+
+    if compiler.is_too_big() {
+        return Err(Error::CompiledTooBig(config.size_limit));
+    }
+    compiler.names.resize(slots / 2, None);
+    Ok(Program {
+        insts: compiler.insts,
+        slots,
+        names: compiler.names.into(),
+        utf8: !config.bytes,
+    })
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+    next_group: usize,
+    // neither '.' nor a negated bracket expression can match these
+    line_terminator: CharClass,
+    // roughly how many bytes the program takes
+    size: usize,
+    size_limit: usize,
+    names: Vec<Option<String>>,
+    // '.' and the classes match single bytes, the chars up to 0xff stand for them
+    byte_classes: bool,
+    // everything is emitted back to front, see `compile_reverse`
+    reverse: bool,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.size += std::mem::size_of::<Inst>();
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    // repetitions stop emitting copies once this is true so a huge count can't eat the memory
+    fn is_too_big(&self) -> bool {
+        self.size > self.size_limit
+    }
+
+    // split and jump targets are not known till the body is emitted
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.insts[at] {
+            Inst::Split(_, second) => *second = target,
+            Inst::Jump(to) => *to = target,
+            _ => unreachable!("only splits and jumps are patched"),
+        }
+    }
+
+    fn sequence(&mut self, patterns: &[Pattern]) {
+        if self.reverse {
+            patterns
+                .iter()
+                .rev()
+                .for_each(|pattern| self.pattern(pattern));
+        } else {
+            patterns.iter().for_each(|pattern| self.pattern(pattern));
+        }
+    }
+
+    // the slots that are saved first and last for a group, swapped when going back
+    fn slots(&self, group: usize) -> (usize, usize) {
+        if self.reverse {
+            (group * 2 + 1, group * 2)
+        } else {
+            (group * 2, group * 2 + 1)
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        let sub_pattern = &pattern.sub_pattern;
+        // every copy of a repeated group has to write into the same slots
+        let first_group = self.next_group;
+
+        match pattern.repetition {
+            Repetition::None => self.sub_pattern(sub_pattern),
+            Repetition::AtMostOnce => self.optional(sub_pattern),
+            Repetition::ZeroOrMore => self.star(sub_pattern),
+            Repetition::AtLeastOnce => {
+                let start = self.insts.len();
+                self.sub_pattern(sub_pattern);
+                let next = self.insts.len() + 1;
+                self.push(Inst::Split(start, next));
+            }
+            Repetition::Exactly(n) => self.copies(sub_pattern, n, first_group),
+            Repetition::AtLeast(n) => {
+                self.copies(sub_pattern, n, first_group);
+                self.next_group = first_group;
+                self.star(sub_pattern);
+            }
+            Repetition::AtMost(m) => self.optional_copies(sub_pattern, m, first_group),
+            Repetition::InRange(n, m) => {
+                self.copies(sub_pattern, n, first_group);
+                self.optional_copies(sub_pattern, m.saturating_sub(n), first_group);
+            }
+        }
+
+        // nothing was emitted for zero copies but the groups inside still own their numbers
+        self.next_group = first_group + count_groups(sub_pattern);
+    }
+
+    fn copies(&mut self, sub_pattern: &SubPattern, n: usize, first_group: usize) {
+        for _ in 0..n {
+            if self.is_too_big() {
+                return;
+            }
+            self.next_group = first_group;
+            self.sub_pattern(sub_pattern);
+        }
+    }
+
+    // x{,m} is nested as (x(x(x)?)?)? so that the engines never retry shorter counts first
+    fn optional_copies(&mut self, sub_pattern: &SubPattern, m: usize, first_group: usize) {
+        let mut splits = Vec::with_capacity(m);
+        for _ in 0..m {
+            if self.is_too_big() {
+                break;
+            }
+            self.next_group = first_group;
+            let next = self.insts.len() + 1;
+            splits.push(self.push(Inst::Split(next, 0)));
+            self.sub_pattern(sub_pattern);
+        }
+        let end = self.insts.len();
+        for split in splits {
+            self.patch(split, end);
+        }
+    }
+
+    fn optional(&mut self, sub_pattern: &SubPattern) {
+        let next = self.insts.len() + 1;
+        let split = self.push(Inst::Split(next, 0));
+        self.sub_pattern(sub_pattern);
+        let end = self.insts.len();
+        self.patch(split, end);
+    }
+
+    fn star(&mut self, sub_pattern: &SubPattern) {
+        let next = self.insts.len() + 1;
+        let split = self.push(Inst::Split(next, 0));
+        self.sub_pattern(sub_pattern);
+        self.push(Inst::Jump(split));
+        let end = self.insts.len();
+        self.patch(split, end);
+    }
+
+    // a capturing group, its alternatives are tried in order
+    fn group(&mut self, alternates: &[Vec<Pattern>]) {
+        let group = self.next_group;
+        self.next_group += 1;
+
+        let (open, close) = self.slots(group);
+        self.push(Inst::Save(open));
+        let mut jumps = Vec::with_capacity(alternates.len());
+        for (i, alternate) in alternates.iter().enumerate() {
+            if i + 1 < alternates.len() {
+                let next = self.insts.len() + 1;
+                let split = self.push(Inst::Split(next, 0));
+                self.sequence(alternate);
+                jumps.push(self.push(Inst::Jump(0)));
+                let other = self.insts.len();
+                self.patch(split, other);
+            } else {
+                self.sequence(alternate);
+            }
+        }
+        let end = self.insts.len();
+        for jump in jumps {
+            self.patch(jump, end);
+        }
+        self.push(Inst::Save(close));
+    }
+
+    fn sub_pattern(&mut self, sub_pattern: &SubPattern) {
+        match sub_pattern {
+            SubPattern::Dot => self.class(&self.line_terminator.negate()),
+            SubPattern::AnyChar => self.class(&CharClass::new([('\0', char::MAX)])),
+            SubPattern::Char(c) => {
+                let mut bytes = c.encode_utf8(&mut [0; 4]).as_bytes().to_vec();
+                if self.reverse {
+                    bytes.reverse();
+                }
+                for byte in bytes {
+                    self.push(Inst::Byte(byte, byte));
+                }
+            }
+            SubPattern::BracketedSet(sets) => self.class(&CharClass::from_sets(sets)),
+            SubPattern::InvertedSet(sets) => {
+                let class = CharClass::from_sets(sets)
+                    .union(&self.line_terminator)
+                    .negate();
+                self.class(&class);
+            }
+            SubPattern::Alternative(alternates) => self.group(alternates),
+            SubPattern::NamedGroup(name, alternates) => {
+                let group = self.next_group;
+                if self.names.len() <= group {
+                    self.names.resize(group + 1, None);
+                }
+                self.names[group] = Some(name.clone());
+                self.group(alternates);
+            }
+            SubPattern::Repeated(pattern) => self.pattern(pattern),
+        }
+    }
+
+    fn class(&mut self, class: &CharClass) {
+        let mut sequences = Vec::new();
+        for &(start, end) in class.ranges() {
+            if !self.byte_classes {
+                utf8_sequences(start as u32, end as u32, &mut sequences);
+            } else if start <= '\u{ff}' {
+                sequences.push(vec![(start as u8, end.min('\u{ff}') as u8)]);
+            }
+        }
+        // backwards the continuation bytes come first, sorted the same ones end up next to
+        // each other. The sequences never match the same bytes so their order doesn't matter.
+        if self.reverse {
+            sequences.iter_mut().for_each(|sequence| sequence.reverse());
+            sequences.sort_unstable();
+        }
+        let sequences: Vec<&[(u8, u8)]> = sequences.iter().map(Vec::as_slice).collect();
+        if sequences.is_empty() {
+            self.push(Inst::Fail);
+        } else {
+            self.byte_sequences(&sequences);
+        }
+    }
+
+    // An alternation of byte sequences, the ones next to each other that start with the
+    // same range share it so the big Unicode classes don't start a thread per sequence
+    fn byte_sequences(&mut self, sequences: &[&[(u8, u8)]]) {
+        let mut jumps = Vec::new();
+        let mut rest = sequences;
+
+        while let Some(first) = rest.first() {
+            let (start, end) = first[0];
+            let same = rest.iter().take_while(|s| s[0] == (start, end)).count();
+            let (group, others) = rest.split_at(same);
+            rest = others;
+
+            let split = (!rest.is_empty()).then(|| {
+                let next = self.insts.len() + 1;
+                self.push(Inst::Split(next, 0))
+            });
+            self.push(Inst::Byte(start, end));
+            // no sequence of a group ends before the others, UTF-8 tells the length by the
+            // lead byte and that is never a continuation byte, back to front neither
+            let tails: Vec<&[(u8, u8)]> = group
+                .iter()
+                .map(|s| &s[1..])
+                .filter(|s| !s.is_empty())
+                .collect();
+            if !tails.is_empty() {
+                self.byte_sequences(&tails);
+            }
+            if let Some(split) = split {
+                jumps.push(self.push(Inst::Jump(0)));
+                let next = self.insts.len();
+                self.patch(split, next);
+            }
+        }
+
+        let end = self.insts.len();
+        for jump in jumps {
+            self.patch(jump, end);
+        }
+    }
+}
+
+// The UTF-8 encodings of the chars from `start` to `end` as sequences of byte ranges. The
+// range is split till every part encodes to the same length and its ends only differ in
+// bytes where the whole range of continuation bytes is covered, then the bytes of the two
+// ends make up the sequence.
+fn utf8_sequences(start: u32, end: u32, sequences: &mut Vec<Vec<(u8, u8)>>) {
+    // the surrogates are no chars, there is nothing to encode for them
+    if start < 0xd800 && end > 0xdfff {
+        utf8_sequences(start, 0xd7ff, sequences);
+        utf8_sequences(0xe000, end, sequences);
+        return;
+    }
+    for max in [0x7f, 0x7ff, 0xffff] {
+        if start <= max && max < end {
+            utf8_sequences(start, max, sequences);
+            utf8_sequences(max + 1, end, sequences);
+            return;
+        }
+    }
+    if end <= 0x7f {
+        sequences.push(vec![(start as u8, end as u8)]);
+        return;
+    }
+    for i in 1..4 {
+        let m = (1 << (6 * i)) - 1;
+        if start & !m != end & !m {
+            if start & m != 0 {
+                utf8_sequences(start, start | m, sequences);
+                utf8_sequences((start | m) + 1, end, sequences);
+                return;
+            }
+            if end & m != m {
+                utf8_sequences(start, (end & !m) - 1, sequences);
+                utf8_sequences(end & !m, end, sequences);
+                return;
+            }
+        }
+    }
+
+    let encode = |c: u32, buffer: &mut [u8; 4]| {
+        let c = char::from_u32(c).expect("the surrogates are split off");
+        c.encode_utf8(buffer).len()
+    };
+    let (mut first, mut last) = ([0; 4], [0; 4]);
+    let len = encode(start, &mut first);
+    encode(end, &mut last);
+    sequences.push(first[..len].iter().copied().zip(last).collect());
+}
+
+fn count_groups(sub_pattern: &SubPattern) -> usize {
+    match sub_pattern {
+        SubPattern::Alternative(alternates) | SubPattern::NamedGroup(_, alternates) => {
+            1 + alternates
+                .iter()
+                .flatten()
+                .map(|pattern| count_groups(&pattern.sub_pattern))
+                .sum::<usize>()
+        }
+        SubPattern::Repeated(pattern) => count_groups(&pattern.sub_pattern),
+        _ => 0,
+    }
+}
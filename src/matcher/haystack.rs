@@ -0,0 +1,318 @@
+// What the engines search in. A haystack doesn't have to be in one piece, a rope of an
+// editor is a list of chunks and the cursors walk over them byte by byte in both directions
+// without ever copying them together.
+
+use std::ops::Range;
+
+use super::bytes;
+use super::compile::Program;
+use super::pikevm::{self, SearchKind};
+use super::{Config, Regex};
+
+pub trait Haystack {
+    fn chunk_count(&self) -> usize;
+
+    // the chunks in order make up the haystack, empty ones are skipped over
+    fn chunk(&self, i: usize) -> &[u8];
+
+    fn len(&self) -> usize {
+        (0..self.chunk_count()).map(|i| self.chunk(i).len()).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the bytes from `pos` on
+    fn cursor(&self, pos: usize) -> Cursor<'_, Self> {
+        Cursor::new(self, pos)
+    }
+
+    // the bytes before `pos` from the last one back
+    fn rev_cursor(&self, pos: usize) -> RevCursor<'_, Self> {
+        RevCursor::new(self, pos)
+    }
+}
+
+impl Haystack for [u8] {
+    fn chunk_count(&self) -> usize {
+        1
+    }
+
+    fn chunk(&self, _: usize) -> &[u8] {
+        self
+    }
+
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+}
+
+impl Haystack for str {
+    fn chunk_count(&self) -> usize {
+        1
+    }
+
+    fn chunk(&self, _: usize) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn len(&self) -> usize {
+        str::len(self)
+    }
+}
+
+impl Haystack for [&str] {
+    fn chunk_count(&self) -> usize {
+        <[&str]>::len(self)
+    }
+
+    fn chunk(&self, i: usize) -> &[u8] {
+        self[i].as_bytes()
+    }
+}
+
+impl<H: Haystack + ?Sized> Haystack for &H {
+    fn chunk_count(&self) -> usize {
+        (**self).chunk_count()
+    }
+
+    fn chunk(&self, i: usize) -> &[u8] {
+        (**self).chunk(i)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+}
+
+// Goes forward from a position, `bytes[offset]` is the next byte unless it is at the end
+pub struct Cursor<'h, H: ?Sized> {
+    haystack: &'h H,
+    chunk: usize,
+    bytes: &'h [u8],
+    offset: usize,
+    pos: usize,
+}
+
+// Goes back from a position, `bytes[offset - 1]` is the byte before it unless it is at the
+// start
+pub struct RevCursor<'h, H: ?Sized> {
+    haystack: &'h H,
+    chunk: usize,
+    bytes: &'h [u8],
+    offset: usize,
+    pos: usize,
+}
+
+impl<'h, H: Haystack + ?Sized> Cursor<'h, H> {
+    fn new(haystack: &'h H, pos: usize) -> Self {
+        let mut skipped = 0;
+        for chunk in 0..haystack.chunk_count() {
+            let bytes = haystack.chunk(chunk);
+            if pos < skipped + bytes.len() {
+                return Cursor {
+                    haystack,
+                    chunk,
+                    bytes,
+                    offset: pos - skipped,
+                    pos,
+                };
+            }
+            skipped += bytes.len();
+        }
+        assert!(pos <= skipped, "position {pos} is past the end");
+        Cursor {
+            haystack,
+            chunk: haystack.chunk_count(),
+            bytes: &[],
+            offset: 0,
+            pos,
+        }
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.offset).copied()
+    }
+}
+
+impl<H: Haystack + ?Sized> Iterator for Cursor<'_, H> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.offset += 1;
+        self.pos += 1;
+        while self.offset == self.bytes.len() && self.chunk < self.haystack.chunk_count() {
+            self.chunk += 1;
+            self.bytes = if self.chunk < self.haystack.chunk_count() {
+                self.haystack.chunk(self.chunk)
+            } else {
+                &[]
+            };
+            self.offset = 0;
+        }
+        Some(byte)
+    }
+}
+
+impl<'h, H: Haystack + ?Sized> RevCursor<'h, H> {
+    fn new(haystack: &'h H, pos: usize) -> Self {
+        let mut skipped = 0;
+        for chunk in 0..haystack.chunk_count() {
+            let bytes = haystack.chunk(chunk);
+            if pos > skipped && pos <= skipped + bytes.len() {
+                return RevCursor {
+                    haystack,
+                    chunk,
+                    bytes,
+                    offset: pos - skipped,
+                    pos,
+                };
+            }
+            skipped += bytes.len();
+        }
+        assert!(pos <= skipped, "position {pos} is past the end");
+        RevCursor {
+            haystack,
+            chunk: 0,
+            bytes: &[],
+            offset: 0,
+            pos,
+        }
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.offset.checked_sub(1).map(|i| self.bytes[i])
+    }
+}
+
+impl<H: Haystack + ?Sized> Iterator for RevCursor<'_, H> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.offset -= 1;
+        self.pos -= 1;
+        while self.offset == 0 && self.chunk > 0 {
+            self.chunk -= 1;
+            self.bytes = self.haystack.chunk(self.chunk);
+            self.offset = self.bytes.len();
+        }
+        Some(byte)
+    }
+}
+
+impl Regex {
+    pub fn is_match_in<H: Haystack + ?Sized>(&self, haystack: &H) -> bool {
+        self.find_in(haystack).is_some()
+    }
+
+    // the span of the leftmost match, the same as `find` on all the chunks put together
+    pub fn find_in<H: Haystack + ?Sized>(&self, haystack: &H) -> Option<Range<usize>> {
+        self.find_in_at(haystack, 0)
+    }
+
+    pub fn find_in_at<H: Haystack + ?Sized>(
+        &self,
+        haystack: &H,
+        start: usize,
+    ) -> Option<Range<usize>> {
+        find(&self.program, self.config, haystack, start)
+    }
+}
+
+impl bytes::Regex {
+    pub fn is_match_in<H: Haystack + ?Sized>(&self, haystack: &H) -> bool {
+        self.find_in(haystack).is_some()
+    }
+
+    pub fn find_in<H: Haystack + ?Sized>(&self, haystack: &H) -> Option<Range<usize>> {
+        self.find_in_at(haystack, 0)
+    }
+
+    pub fn find_in_at<H: Haystack + ?Sized>(
+        &self,
+        haystack: &H,
+        start: usize,
+    ) -> Option<Range<usize>> {
+        find(&self.program, self.config, haystack, start)
+    }
+}
+
+fn find<H: Haystack + ?Sized>(
+    program: &Program,
+    config: Config,
+    haystack: &H,
+    start: usize,
+) -> Option<Range<usize>> {
+    let kind = if config.anchored {
+        SearchKind::Prefix
+    } else {
+        SearchKind::Unanchored
+    };
+    let slots = pikevm::search(program, config.semantics, haystack, start, kind)?;
+    Some(slots[0]?..slots[1]?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cursors_over_chunks() {
+        let rope: &[&str] = &["ab", "", "c", "", "", "de", ""];
+        assert_eq!(Haystack::len(rope), 5);
+        assert_eq!(rope.cursor(0).collect::<Vec<_>>(), b"abcde");
+        assert_eq!(rope.cursor(2).collect::<Vec<_>>(), b"cde");
+        assert_eq!(rope.cursor(5).next(), None);
+        assert_eq!(rope.rev_cursor(5).collect::<Vec<_>>(), b"edcba");
+        assert_eq!(rope.rev_cursor(3).collect::<Vec<_>>(), b"cba");
+        assert_eq!(rope.rev_cursor(0).next(), None);
+
+        let mut cursor = rope.cursor(1);
+        assert_eq!(
+            (cursor.next(), cursor.peek(), cursor.pos()),
+            (Some(b'b'), Some(b'c'), 2)
+        );
+        let mut cursor = rope.rev_cursor(3);
+        assert_eq!(
+            (cursor.next(), cursor.peek(), cursor.pos()),
+            (Some(b'c'), Some(b'b'), 2)
+        );
+
+        let empty: &[&str] = &[];
+        assert!(Haystack::is_empty(empty) && empty.cursor(0).next().is_none());
+        assert_eq!("héllo".rev_cursor(3).count(), 3);
+        assert_eq!(b"xyz"[..].cursor(1).collect::<Vec<_>>(), b"yz");
+    }
+
+    #[test]
+    fn searching_chunks() {
+        let re = Regex::new("(fo+|ba)r$").unwrap();
+        let rope: &[&str] = &["a f", "o", "", "ob", "ar ", "fooo", "r"];
+        assert_eq!(rope.concat(), "a foobar fooor");
+        assert_eq!(re.find_in(rope), Some(9..14));
+        assert_eq!(re.find_in_at(rope, 10), None);
+        assert_eq!(re.find_in(&"foobar"), re.find("foobar").map(|m| m.range()));
+
+        // the chars that are split between chunks are still whole
+        let re = Regex::new("é+").unwrap();
+        let rope: &[&str] = &["aé", "éb"];
+        let bytes = "aééb".as_bytes();
+        assert_eq!(re.find_in(rope), Some(1..5));
+        assert_eq!(re.find_in(bytes), Some(1..5));
+
+        let re = bytes::Regex::new(r"\xff.").unwrap();
+        assert_eq!(re.find_in(&b"a\xff\x00"[..]), Some(1..3));
+        assert!(!re.is_match_in(&["\u{ff}"][..]));
+    }
+}
@@ -1,6 +1,1056 @@
-use crate::parser::Expression;
+use std::ops::{Index, Range};
+use std::sync::{Arc, OnceLock};
+
+use crate::parser::{self, Expression, Options, Syntax};
+
+pub mod bytes;
+mod compile;
+mod haystack;
+mod overlapping;
+mod pikevm;
+mod replace;
+mod reverse;
+mod set;
+mod split;
+mod stream;
+
+use compile::Program;
+pub use haystack::{Cursor, Haystack, RevCursor};
+pub use overlapping::OverlappingMatches;
+use pikevm::{SearchKind, Slots};
+pub use replace::Replacer;
+pub use reverse::RMatches;
+pub use set::{RegexSet, SetMatches, SetMatchesIter};
+pub use split::{Split, SplitCaptures, SplitN};
+pub use stream::{StreamMatch, StreamMatches};
+
+// Which match wins when more than one is possible at the leftmost position
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Semantics {
+    // like perl and most backtracking engines, the first alternative that matches wins
+    #[default]
+    LeftmostFirst,
+    // like `grep` and `regexec`, the longest match wins and so do the longest submatches
+    LeftmostLongest,
+}
+
+// What ends a line, '.' and negated bracket expressions never match it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineTerminator {
+    #[default]
+    Newline,
+    CrLf,
+    // for data where records are separated by NUL like `grep -z`
+    Nul,
+}
+
+impl LineTerminator {
+    pub(crate) fn chars(&self) -> &'static [char] {
+        match self {
+            LineTerminator::Newline => &['\n'],
+            LineTerminator::CrLf => &['\r', '\n'],
+            LineTerminator::Nul => &['\0'],
+        }
+    }
+}
+
+// Options that are not about parsing, they decide how the program is built and run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Config {
+    semantics: Semantics,
+    line_terminator: LineTerminator,
+    // '^' and '$' match at the start and end of every line
+    multi_line: bool,
+    // in bytes, the compiled program can't get bigger than that
+    size_limit: usize,
+    // matches have to start right where the search starts
+    anchored: bool,
+    // the haystack is `&[u8]` so a match can start in the middle of a char
+    bytes: bool,
+    // '.' and the classes match single bytes, for `bytes::Regex` without unicode
+    byte_classes: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            semantics: Semantics::default(),
+            line_terminator: LineTerminator::default(),
+            multi_line: false,
+            size_limit: 10 * (1 << 20),
+            anchored: false,
+            bytes: false,
+            byte_classes: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Parse(parser::Error),
+    // the size limit that the compiled pattern went over
+    CompiledTooBig(usize),
+}
+
+impl From<parser::Error> for Error {
+    fn from(error: parser::Error) -> Self {
+        Error::Parse(error)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(error) => error.fmt(f),
+            Error::CompiledTooBig(limit) => {
+                write!(
+                    f,
+                    "compiled pattern is bigger than the limit of {limit} bytes"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
 
 // This will contain all the state associated with matching agianst the text
-struct Matcher {
-    expression: Expression,
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    program: Program,
+    // the same pattern back to front, built the first time `rfind` needs it
+    reverse: OnceLock<Program>,
+    options: Options,
+    config: Config,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    options: Options,
+    config: Config,
+}
+
+// The haystacks a match can be in, `str` for `Regex` and `[u8]` for `bytes::Regex`. After
+// an empty match the search goes on at the next char or at the next byte.
+pub trait Text: Haystack + Index<Range<usize>, Output = Self> {
+    fn next_boundary(&self, pos: usize) -> usize;
+}
+
+impl Text for str {
+    fn next_boundary(&self, pos: usize) -> usize {
+        pos + self[pos..].chars().next().map_or(1, char::len_utf8)
+    }
+}
+
+impl Text for [u8] {
+    fn next_boundary(&self, pos: usize) -> usize {
+        pos + 1
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match<'h, T: Text + ?Sized = str> {
+    haystack: &'h T,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Captures<'h, T: Text + ?Sized = str> {
+    haystack: &'h T,
+    slots: Slots,
+    names: Arc<[Option<String>]>,
+}
+
+// a derive would want `T: Clone` and `str` isn't
+impl<T: Text + ?Sized> Clone for Match<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Text + ?Sized> Copy for Match<'_, T> {}
+
+impl<T: Text + ?Sized> Clone for Captures<'_, T> {
+    fn clone(&self) -> Self {
+        Captures {
+            haystack: self.haystack,
+            slots: self.slots.clone(),
+            names: self.names.clone(),
+        }
+    }
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            options: Options::default(),
+            config: Config::default(),
+        }
+    }
+
+    pub fn syntax(&mut self, syntax: Syntax) -> &mut Self {
+        self.options.syntax = syntax;
+        self
+    }
+
+    // the same as starting the pattern with (?i)
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.options.case_insensitive = yes;
+        self
+    }
+
+    // '^' and '$' match at the start and the end of every line, not only of the haystack
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.config.multi_line = yes;
+        self
+    }
+
+    // POSIX classes, `\d`, `\w`, `\s` and case insensitivity are Unicode aware, they are
+    // ASCII only by default
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.options.unicode = yes;
+        self
+    }
+
+    // roughly how many bytes the compiled pattern can take, 10MB by default
+    pub fn size_limit(&mut self, bytes: usize) -> &mut Self {
+        self.config.size_limit = bytes;
+        self
+    }
+
+    // how deep groups, nested brackets and stacked repetitions can go, 250 by default
+    pub fn nest_limit(&mut self, limit: usize) -> &mut Self {
+        self.options.nest_limit = limit;
+        self
+    }
+
+    // every search only matches right at its start, `find_iter` then gives back the matches
+    // as long as they follow each other like the tokens of a tokenizer
+    pub fn anchored(&mut self, yes: bool) -> &mut Self {
+        self.config.anchored = yes;
+        self
+    }
+
+    pub fn semantics(&mut self, semantics: Semantics) -> &mut Self {
+        self.config.semantics = semantics;
+        self
+    }
+
+    // the same as starting the pattern with (?s)
+    pub fn dot_matches_newline(&mut self, yes: bool) -> &mut Self {
+        self.options.dot_matches_newline = yes;
+        self
+    }
+
+    // nested brackets and the `&&`, `--` and `~~` operators like in `[[:alnum:]--[0-9]]`
+    pub fn set_operations(&mut self, yes: bool) -> &mut Self {
+        self.options.set_operations = yes;
+        self
+    }
+
+    // a backslash is literal inside of brackets, `[\]` is a set with just the backslash
+    pub fn posix_brackets(&mut self, yes: bool) -> &mut Self {
+        self.options.posix_brackets = yes;
+        self
+    }
+
+    // the same as starting the pattern with (?x)
+    pub fn extended(&mut self, yes: bool) -> &mut Self {
+        self.options.extended = yes;
+        self
+    }
+
+    // `a{x}` or a '{' with nothing before it is an error instead of a literal '{'
+    pub fn strict_intervals(&mut self, yes: bool) -> &mut Self {
+        self.options.strict_intervals = yes;
+        self
+    }
+
+    // the largest count an interval can have, `parser::RE_DUP_MAX` by default
+    pub fn dup_max(&mut self, limit: usize) -> &mut Self {
+        self.options.dup_max = limit;
+        self
+    }
+
+    // a quantifier can follow another one, `a{2}{3}` is `(a{2}){3}` without the group
+    pub fn stacked_repetitions(&mut self, yes: bool) -> &mut Self {
+        self.options.stacked_repetitions = yes;
+        self
+    }
+
+    // with `false` empty groups and alternatives like `()` or `(a|)` are an error
+    pub fn empty_alternatives(&mut self, yes: bool) -> &mut Self {
+        self.options.empty_alternatives = yes;
+        self
+    }
+
+    pub fn line_terminator(&mut self, line_terminator: LineTerminator) -> &mut Self {
+        self.config.line_terminator = line_terminator;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, Error> {
+        let expression = parser::process_with(&self.pattern, &self.options)?;
+        Regex::from_expression(&self.pattern, &expression, self.options, self.config)
+    }
+
+    // a regex for `&[u8]` haystacks, without unicode its classes match single bytes
+    pub fn build_bytes(&self) -> Result<bytes::Regex, Error> {
+        let options = Options {
+            bytes: true,
+            ..self.options
+        };
+        let expression = parser::process_with(&self.pattern, &options)?;
+        let config = Config {
+            bytes: true,
+            byte_classes: options.byte_classes(),
+            ..self.config
+        };
+        bytes::Regex::from_expression(&self.pattern, &expression, options, config)
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        let expression = parser::process(pattern)?;
+        Regex::from_expression(pattern, &expression, Options::default(), Config::default())
+    }
+
+    fn from_expression(
+        pattern: &str,
+        expression: &Expression,
+        options: Options,
+        config: Config,
+    ) -> Result<Regex, Error> {
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program: compile::compile(expression, &config)?,
+            reverse: OnceLock::new(),
+            options,
+            config,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    // the number of groups including the implicit one for the whole match
+    pub fn captures_len(&self) -> usize {
+        self.program.slots / 2
+    }
+
+    // the names of the groups in order, None for the ones without a name and the whole match
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        self.program.names.iter().map(|name| name.as_deref())
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.is_match_at(haystack, 0)
+    }
+
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    // the search starts at `start` but '^' and the other assertions still see what is
+    // before it, unlike searching in `&haystack[start..]`
+    pub fn is_match_at(&self, haystack: &str, start: usize) -> bool {
+        self.find_at(haystack, start).is_some()
+    }
+
+    pub fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        self.captures_at(haystack, start)
+            .and_then(|captures| captures.get(0))
+    }
+
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        self.captures_at(haystack, 0)
+    }
+
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        Matches(self.captures_iter(haystack))
+    }
+
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h str) -> CaptureMatches<'r, 'h> {
+        CaptureMatches::new(&self.program, self.config, haystack)
+    }
+
+    pub fn captures_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Captures<'h>> {
+        search(
+            &self.program,
+            self.config,
+            haystack,
+            start,
+            self.config.search_kind(),
+        )
+    }
+
+    // the whole haystack has to match, like the pattern was in `^...$`
+    pub fn is_full_match(&self, haystack: &str) -> bool {
+        self.full_match(haystack).is_some()
+    }
+
+    pub fn full_match<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        search(&self.program, self.config, haystack, 0, SearchKind::Full)
+    }
+
+    // the match has to start at the beginning of the haystack but can end anywhere, like
+    // `re.match` in python
+    pub fn match_prefix<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        search(&self.program, self.config, haystack, 0, SearchKind::Prefix)
+    }
+}
+
+// what `Regex` and `bytes::Regex` both search with
+fn search<'h, T: Text + ?Sized>(
+    program: &Program,
+    config: Config,
+    haystack: &'h T,
+    start: usize,
+    kind: SearchKind,
+) -> Option<Captures<'h, T>> {
+    pikevm::search(program, config.semantics, haystack, start, kind).map(|slots| Captures {
+        haystack,
+        slots,
+        names: program.names.clone(),
+    })
+}
+
+impl Config {
+    // the kind of search `find` and the iterators do
+    fn search_kind(&self) -> SearchKind {
+        if self.anchored {
+            SearchKind::Prefix
+        } else {
+            SearchKind::Unanchored
+        }
+    }
+}
+
+impl<'h, T: Text + ?Sized> Match<'h, T> {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+impl<'h> Match<'h> {
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+impl<'h> Match<'h, [u8]> {
+    pub fn as_bytes(&self) -> &'h [u8] {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+impl<'h, T: Text + ?Sized> Captures<'h, T> {
+    // None when the group did not take part in the match
+    pub fn get(&self, i: usize) -> Option<Match<'h, T>> {
+        match (self.slots.get(i * 2)?, self.slots.get(i * 2 + 1)?) {
+            (Some(start), Some(end)) => Some(Match {
+                haystack: self.haystack,
+                start: *start,
+                end: *end,
+            }),
+            _ => None,
+        }
+    }
+
+    // the first group with the name that took part in the match
+    pub fn name(&self, name: &str) -> Option<Match<'h, T>> {
+        self.names
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.as_deref() == Some(name))
+            .find_map(|(i, _)| self.get(i))
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+impl<T: Text + ?Sized> Index<usize> for Captures<'_, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        self.get(i)
+            .map(|m| &m.haystack[m.range()])
+            .unwrap_or_else(|| panic!("no group at index {i}"))
+    }
+}
+
+pub struct CaptureMatches<'r, 'h, T: Text + ?Sized = str> {
+    program: &'r Program,
+    config: Config,
+    haystack: &'h T,
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl<'r, 'h, T: Text + ?Sized> CaptureMatches<'r, 'h, T> {
+    fn new(program: &'r Program, config: Config, haystack: &'h T) -> Self {
+        CaptureMatches {
+            program,
+            config,
+            haystack,
+            pos: 0,
+            last_end: None,
+        }
+    }
+}
+
+impl<'h, T: Text + ?Sized> Iterator for CaptureMatches<'_, 'h, T> {
+    type Item = Captures<'h, T>;
+
+    fn next(&mut self) -> Option<Captures<'h, T>> {
+        loop {
+            if self.pos > self.haystack.len() {
+                return None;
+            }
+            let kind = self.config.search_kind();
+            let captures = search(self.program, self.config, self.haystack, self.pos, kind)?;
+            let found = captures.get(0)?;
+
+            // an empty match right where the last one ended is not a new match
+            if found.is_empty() && Some(found.end) == self.last_end {
+                self.pos = self.haystack.next_boundary(found.end);
+                continue;
+            }
+
+            self.pos = if found.is_empty() {
+                self.haystack.next_boundary(found.end)
+            } else {
+                found.end
+            };
+            self.last_end = Some(found.end);
+            return Some(captures);
+        }
+    }
+}
+
+pub struct Matches<'r, 'h, T: Text + ?Sized = str>(CaptureMatches<'r, 'h, T>);
+
+impl<'h, T: Text + ?Sized> Iterator for Matches<'_, 'h, T> {
+    type Item = Match<'h, T>;
+
+    fn next(&mut self) -> Option<Match<'h, T>> {
+        self.0.next().and_then(|captures| captures.get(0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spans(captures: &Captures<'_>) -> Vec<Option<(usize, usize)>> {
+        (0..captures.len())
+            .map(|i| captures.get(i).map(|m| (m.start(), m.end())))
+            .collect()
+    }
+
+    fn posix(pattern: &str) -> Regex {
+        RegexBuilder::new(pattern)
+            .semantics(Semantics::LeftmostLongest)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn finding_simple_matches() {
+        let re = Regex::new(r"[a-z]+@[a-z]+\.[a-z]{2,8}").unwrap();
+        let found = re.find("mail me at bob@example.com please").unwrap();
+        assert_eq!(found.as_str(), "bob@example.com");
+        assert_eq!(found.range(), 11..26);
+
+        assert!(Regex::new(r"^cat$").unwrap().is_match("cat"));
+        assert!(!Regex::new(r"^cat$").unwrap().is_match("cats"));
+        assert!(!Regex::new(r"^[^[:digit:]]+$").unwrap().is_match("abc1"));
+    }
+
+    #[test]
+    fn finding_all_matches() {
+        let re = Regex::new(r"a*").unwrap();
+        let found: Vec<_> = re.find_iter("baaab").map(|m| m.range()).collect();
+        assert_eq!(found, vec![0..0, 1..4, 5..5]);
+    }
+
+    #[test]
+    fn leftmost_first_alternation() {
+        let re = Regex::new(r"(a|ab)(c|bcd)(d*)").unwrap();
+        let captures = re.captures("abcd").unwrap();
+        assert_eq!(
+            spans(&captures),
+            vec![Some((0, 4)), Some((0, 1)), Some((1, 4)), Some((4, 4))]
+        );
+
+        let re = Regex::new(r"(sam|samwise)").unwrap();
+        assert_eq!(re.find("samwise").unwrap().as_str(), "sam");
+    }
+
+    #[test]
+    fn leftmost_longest_alternation() {
+        let captures = posix(r"(a|ab)(c|bcd)(d*)").captures("abcd").unwrap();
+        assert_eq!(
+            spans(&captures),
+            vec![Some((0, 4)), Some((0, 2)), Some((2, 3)), Some((3, 4))]
+        );
+
+        assert_eq!(
+            posix(r"(sam|samwise)").find("samwise").unwrap().as_str(),
+            "samwise"
+        );
+        // leftmost still comes before longest
+        assert_eq!(posix(r"(b|abc)").find("xabc").unwrap().as_str(), "abc");
+        assert_eq!(posix(r"(bcd|ab)").find("abcd").unwrap().as_str(), "ab");
+    }
+
+    #[test]
+    fn leftmost_longest_submatches() {
+        let captures = posix(r"(a*)(a*)").captures("aaa").unwrap();
+        assert_eq!(
+            spans(&captures),
+            vec![Some((0, 3)), Some((0, 3)), Some((3, 3))]
+        );
+
+        // the last iteration of a repeated group is the one reported
+        let captures = posix(r"(a|b)*").captures("ab").unwrap();
+        assert_eq!(spans(&captures), vec![Some((0, 2)), Some((1, 2))]);
+    }
+
+    #[test]
+    fn basic_syntax() {
+        let re = RegexBuilder::new(r"^\(ab\)\{2\}+$")
+            .syntax(Syntax::Basic)
+            .build()
+            .unwrap();
+        assert!(re.is_match("abab+"));
+        assert!(!re.is_match("ababab"));
+    }
+
+    #[test]
+    fn dot_and_newline() {
+        let re = Regex::new(r"a.b").unwrap();
+        assert!(re.is_match("a-b"));
+        assert!(!re.is_match("a\nb"));
+        assert!(Regex::new(r"(?s)a.b").unwrap().is_match("a\nb"));
+        assert!(RegexBuilder::new(r"a.b")
+            .dot_matches_newline(true)
+            .build()
+            .unwrap()
+            .is_match("a\nb"));
+        assert!(!Regex::new(r"a[^x]b").unwrap().is_match("a\nb"));
+    }
+
+    #[test]
+    fn other_line_terminators() {
+        let crlf = RegexBuilder::new(r"a.+")
+            .line_terminator(LineTerminator::CrLf)
+            .build()
+            .unwrap();
+        assert_eq!(crlf.find("abc\r\n").unwrap().as_str(), "abc");
+
+        let nul = RegexBuilder::new(r"a[^x]+")
+            .line_terminator(LineTerminator::Nul)
+            .build()
+            .unwrap();
+        assert_eq!(nul.find("ab\nc\0d").unwrap().as_str(), "ab\nc");
+    }
+
+    #[test]
+    fn unicode_haystacks() {
+        let re = Regex::new("caf.").unwrap();
+        let found = re.find("un café noir").unwrap();
+        assert_eq!(found.as_str(), "café");
+        assert_eq!(found.range(), 3..8);
+
+        let found: Vec<_> = Regex::new("(日|本)")
+            .unwrap()
+            .find_iter("日本語")
+            .map(|m| m.as_str())
+            .collect();
+        assert_eq!(found, vec!["日", "本"]);
+
+        // empty matches only happen on char boundaries
+        let found: Vec<_> = Regex::new("x*")
+            .unwrap()
+            .find_iter("é")
+            .map(|m| m.range())
+            .collect();
+        assert_eq!(found, vec![0..0, 2..2]);
+    }
+
+    #[test]
+    fn unicode_property_classes() {
+        let re = Regex::new(r"\p{Lu}\p{Ll}+").unwrap();
+        assert_eq!(re.find("hello Ωmega").unwrap().as_str(), "Ωmega");
+
+        let re = Regex::new(r"[\p{Han}\p{Hiragana}]+").unwrap();
+        assert_eq!(re.find("ok 日本ですね").unwrap().as_str(), "日本ですね");
+
+        let re = Regex::new(r"\P{L}+").unwrap();
+        assert_eq!(re.find("abc 12\n!x").unwrap().as_str(), " 12\n!");
+
+        assert!(Regex::new(r"^\p{Emoji}$").unwrap().is_match("😀"));
+        assert!(!Regex::new(r"[^\p{Greek}]").unwrap().is_match("αβγ"));
+    }
+
+    #[test]
+    fn bracket_set_operations() {
+        let build = |pattern| {
+            RegexBuilder::new(pattern)
+                .set_operations(true)
+                .build()
+                .unwrap()
+        };
+
+        let consonants = build(r"[a-z--[aeiou]]+");
+        assert_eq!(consonants.find("queue strength").unwrap().as_str(), "q");
+        assert_eq!(
+            build(r"[[:alnum:]_--[:digit:]]+")
+                .find("12ab_3")
+                .unwrap()
+                .as_str(),
+            "ab_"
+        );
+        assert!(build(r"^[\p{Greek}&&\p{Lu}]+$").is_match("ΑΒΓ"));
+        assert!(!build(r"^[\p{Greek}&&\p{Lu}]+$").is_match("αβγ"));
+        assert!(!build(r"[^a-z&&[^aeiou]]").is_match("bcd"));
+
+        // without the option these stay plain characters
+        assert!(Regex::new(r"[a&&b]").unwrap().is_match("&"));
+        assert!(Regex::new(r"[a[b]]").is_err());
+    }
+
+    #[test]
+    fn posix_bracket_expressions() {
+        let posix = |pattern| {
+            RegexBuilder::new(pattern)
+                .posix_brackets(true)
+                .build()
+                .unwrap()
+        };
+        let matching = |pattern, haystack: &str| {
+            let re = posix(pattern);
+            haystack
+                .chars()
+                .filter(|c| re.is_match(&c.to_string()))
+                .collect::<String>()
+        };
+
+        assert_eq!(matching(r"[]a]", "]ab"), "]a");
+        assert_eq!(matching(r"[^]a]", "]ab"), "b");
+        assert_eq!(matching(r"[a-]", "a-b"), "a-");
+        assert_eq!(matching(r"[-a]", "a-b"), "a-");
+        assert_eq!(matching(r"[]-a]", "]^a-b"), "]^a");
+        assert_eq!(matching(r"[--@]", "-0@,A"), "-0@");
+        assert_eq!(matching(r"[%--]", "%,-.$"), "%,-");
+        assert_eq!(matching(r"[a-a]", "ab"), "a");
+        assert_eq!(matching(r"[\]", "\\n]"), "\\");
+        assert_eq!(matching(r"[\n]", "\\n\n"), "\\n");
+        assert_eq!(matching(r"[[.-.][=a=]]", "-ab."), "-a");
+        assert_eq!(matching(r"[[a]", "[a]"), "[a");
+
+        // the bracket ends at the first ']' after the backslash
+        let re = posix(r"^[a\]]$");
+        assert!(re.is_match("a]") && re.is_match("\\]"));
+        assert!(!re.is_match("]"));
+
+        // without the strict mode the backslash escapes
+        assert!(Regex::new(r"^[a\]]$").unwrap().is_match("]"));
+
+        // and so do the perl classes, they are literal in strict brackets too
+        let re = RegexBuilder::new(r"^[\d]+$")
+            .syntax(Syntax::Perl)
+            .posix_brackets(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("\\d") && !re.is_match("5"));
+    }
+
+    #[test]
+    fn escaped_user_input() {
+        let needle = "1+1=2? (maybe)";
+        let re = Regex::new(&crate::escape(needle)).unwrap();
+        assert_eq!(re.find("is 1+1=2? (maybe) so").unwrap().range(), 3..17);
+        assert!(!re.is_match("11=2 maybe"));
+
+        let re = RegexBuilder::new(&crate::escape("a b\t#c"))
+            .extended(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("a b\t#c"));
+        assert!(!re.is_match("ab"));
+
+        let re = RegexBuilder::new(&crate::escape_for("a+{2}", Syntax::Basic))
+            .syntax(Syntax::Basic)
+            .build()
+            .unwrap();
+        assert!(re.is_match("a+{2}"));
+        assert!(!re.is_match("aa"));
+
+        let re = Regex::new(r"^\Q[a-z]\E+$").unwrap();
+        assert!(re.is_match("[a-z]]]"));
+        assert!(!re.is_match("b"));
+    }
+
+    #[test]
+    fn extended_mode() {
+        let re = RegexBuilder::new(
+            r"
+            ([0-9]{4}) - ([0-9]{2})   # year and month
+            \ at \  ([[:alpha:]]+)  # where
+            ",
+        )
+        .extended(true)
+        .build()
+        .unwrap();
+        let caps = re.captures("on 2022-05 at home").unwrap();
+        assert_eq!(&caps[1], "2022");
+        assert_eq!(&caps[3], "home");
+
+        let error = Regex::new("(?x) a b ( c").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expression was not terminated properly at byte 12"
+        );
+    }
+
+    #[test]
+    fn invalid_intervals() {
+        let re = Regex::new(r"(a{x}|{2}b|c{1)").unwrap();
+        assert_eq!(re.find("xa{x}").unwrap().as_str(), "a{x}");
+        assert_eq!(re.find("{2}b").unwrap().as_str(), "{2}b");
+        assert_eq!(re.find("c{1").unwrap().as_str(), "c{1");
+
+        let strict = |pattern| RegexBuilder::new(pattern).strict_intervals(true).build();
+        assert!(strict("a{x}").is_err());
+        assert!(strict("{2}").is_err());
+        assert!(strict("a{2}").is_ok());
+
+        assert!(Regex::new("a{5,2}").is_err());
+        assert!(RegexBuilder::new("a{3}").dup_max(2).build().is_err());
+    }
+
+    #[test]
+    fn stacked_and_nullable_repetitions() {
+        let stacked = |pattern| {
+            RegexBuilder::new(pattern)
+                .stacked_repetitions(true)
+                .build()
+                .unwrap()
+        };
+        assert!(Regex::new("a{2}{3}").is_err());
+        assert_eq!(stacked("a{2}{3}").find("aaaaaaaa").unwrap().len(), 6);
+        assert_eq!(stacked("^(ab){2}?$").find("ab"), None);
+        assert_eq!(stacked("(ab){2}?").find("abab").unwrap().len(), 4);
+        assert_eq!(stacked("x**").find("xxx").unwrap().len(), 3);
+
+        let haystack = "a".repeat(1000) + "b";
+        for semantics in [Semantics::LeftmostFirst, Semantics::LeftmostLongest] {
+            for pattern in ["(a*)*b", "(a*)+b", "(a|b*)*", "((a*)*)*b", "a***b"] {
+                let re = RegexBuilder::new(pattern)
+                    .stacked_repetitions(true)
+                    .semantics(semantics)
+                    .build()
+                    .unwrap();
+                assert_eq!(re.find(&haystack).unwrap().len(), 1001, "{pattern}");
+            }
+        }
+        let caps = stacked("(a*)*").captures("aa").unwrap();
+        assert_eq!(caps.get(0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn empty_alternatives() {
+        let build = |pattern, semantics| {
+            RegexBuilder::new(pattern)
+                .semantics(semantics)
+                .build()
+                .unwrap()
+        };
+
+        let re = build("(|a)", Semantics::LeftmostFirst);
+        assert_eq!(re.find("a").unwrap().range(), 0..0);
+        let re = build("(|a)", Semantics::LeftmostLongest);
+        assert_eq!(re.captures("a").unwrap().get(1).unwrap().range(), 0..1);
+
+        for semantics in [Semantics::LeftmostFirst, Semantics::LeftmostLongest] {
+            let caps = build("x()y", semantics).captures("xy").unwrap();
+            assert_eq!(caps.get(1).unwrap().range(), 1..1);
+            assert_eq!(build("(a|)b", semantics).find("b").unwrap().range(), 0..1);
+            assert_eq!(
+                build("(|a)*b", semantics).find("aab").unwrap().range(),
+                0..3
+            );
+            for pattern in ["()*", "(|)+", "(()|())*", "(|)*x"] {
+                let re = build(pattern, semantics);
+                assert_eq!(
+                    re.find_iter("xx").count(),
+                    3 - usize::from(pattern.ends_with('x'))
+                );
+            }
+        }
+        assert!(RegexBuilder::new("(a|)")
+            .empty_alternatives(false)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn builder_options() {
+        let re = RegexBuilder::new("straße")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("STRAßE"));
+        assert!(!re.is_match("STRAẞE"));
+        let re = RegexBuilder::new("straße")
+            .case_insensitive(true)
+            .unicode(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("STRAẞE"));
+        let re = Regex::new("a(?i)b[c-d](?-i)e").unwrap();
+        assert!(re.is_match("aBDe") && !re.is_match("AbcE"));
+
+        let re = RegexBuilder::new("^[a-z]+$")
+            .multi_line(true)
+            .build()
+            .unwrap();
+        let lines: Vec<_> = re
+            .find_iter("one\ntwo\n3\nfour")
+            .map(|m| m.as_str())
+            .collect();
+        assert_eq!(lines, ["one", "two", "four"]);
+        let re = RegexBuilder::new("^$")
+            .multi_line(true)
+            .line_terminator(LineTerminator::CrLf)
+            .build()
+            .unwrap();
+        assert_eq!(re.find_iter("a\r\n\r\nb").count(), 1);
+
+        let perl = |pattern, unicode| {
+            RegexBuilder::new(pattern)
+                .syntax(Syntax::Perl)
+                .unicode(unicode)
+                .build()
+                .unwrap()
+        };
+        assert_eq!(
+            perl(r"\w+\s\d", false)
+                .find("héllo wörld 7")
+                .unwrap()
+                .as_str(),
+            "rld 7"
+        );
+        assert_eq!(
+            perl(r"\w+\s\d", true)
+                .find("héllo wörld 7")
+                .unwrap()
+                .as_str(),
+            "wörld 7"
+        );
+        assert_eq!(
+            perl(r"[\D]+", false).find("12ab\n3").unwrap().as_str(),
+            "ab\n"
+        );
+        assert!(Regex::new(r"[[:alpha:]]").unwrap().find("é").is_none());
+        assert!(RegexBuilder::new(r"[[:alpha:]]")
+            .unicode(true)
+            .build()
+            .unwrap()
+            .is_match("é"));
+
+        let error = RegexBuilder::new("a{1000}{1000}")
+            .stacked_repetitions(true)
+            .build()
+            .unwrap_err();
+        assert_eq!(error, Error::CompiledTooBig(10 * (1 << 20)));
+        assert!(RegexBuilder::new("[a-z]{10}")
+            .size_limit(100)
+            .build()
+            .is_err());
+        assert!(RegexBuilder::new("((a))").nest_limit(1).build().is_err());
+        assert!(RegexBuilder::new("((a))").nest_limit(2).build().is_ok());
+    }
+
+    #[test]
+    fn searching_at_an_offset() {
+        // '^' still knows that the search doesn't start at the beginning
+        let re = Regex::new("^a").unwrap();
+        assert!(re.is_match_at("ab", 0));
+        assert!(!re.is_match_at("aa", 1));
+        let re = RegexBuilder::new("^[a-z]")
+            .multi_line(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find_at("ab\ncd", 1).unwrap().range(), 3..4);
+        let re = Regex::new("b").unwrap();
+        assert_eq!(re.find_at("bab", 1).unwrap().range(), 2..3);
+
+        let re = RegexBuilder::new("[0-9]+").anchored(true).build().unwrap();
+        assert_eq!(re.find_at("ab12", 2).unwrap().range(), 2..4);
+        assert!(re.find_at("ab12", 1).is_none());
+        assert!(!re.is_match("ab12"));
+        let caps = re.captures_at("x7", 1).unwrap();
+        assert_eq!(&caps[0], "7");
+
+        // a tokenizer, the matches have to follow each other
+        let re = RegexBuilder::new("([a-z]+|[0-9]+|[[:space:]]+)")
+            .anchored(true)
+            .build()
+            .unwrap();
+        let tokens: Vec<_> = re.find_iter("ab 12 ?cd").map(|m| m.as_str()).collect();
+        assert_eq!(tokens, ["ab", " ", "12", " "]);
+    }
+
+    #[test]
+    fn full_and_prefix_matches() {
+        let re = Regex::new("[0-9]+(px|em)?").unwrap();
+        assert!(re.is_full_match("12px"));
+        assert!(!re.is_full_match("12pt"));
+        assert!(!re.is_full_match(" 12"));
+        assert_eq!(&re.match_prefix("12pt").unwrap()[0], "12");
+        assert!(re.match_prefix(" 12").is_none());
+
+        // leftmost first would stop at "a" but the full match has to go on
+        let re = Regex::new("(a|ab)").unwrap();
+        assert_eq!(re.find("ab").unwrap().as_str(), "a");
+        assert_eq!(&re.full_match("ab").unwrap()[1], "ab");
+
+        let re = RegexBuilder::new("(a|ab)(c|bcd)?")
+            .semantics(Semantics::LeftmostLongest)
+            .build()
+            .unwrap();
+        assert!(re.is_full_match("abcd") && !re.is_full_match("abcdx"));
+        assert!(Regex::new("x*").unwrap().is_full_match(""));
+    }
 }
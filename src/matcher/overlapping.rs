@@ -0,0 +1,206 @@
+// Matches that can overlap, like the motif `ATA` twice in `ATATA`. Every start position
+// gets the match a search that has to start there finds, `a+` in "aaa" gives 0..3, 1..3 and
+// 2..3. With `every_end` every start gets all the ends it can match to, in the order of the
+// ends. It is a single pass of the pike VM where every start has threads of its own.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+
+use super::bytes;
+use super::compile::Program;
+use super::pikevm::Overlapping;
+use super::{Config, Match, Regex, Semantics};
+
+impl Regex {
+    pub fn find_overlapping_iter<'r, 'h>(
+        &'r self,
+        haystack: &'h str,
+    ) -> OverlappingMatches<'r, 'h> {
+        OverlappingMatches {
+            regex: self,
+            haystack,
+            overlaps: Overlaps::new(&self.program, self.config),
+        }
+    }
+}
+
+impl bytes::Regex {
+    pub fn find_overlapping_iter<'r, 'h>(
+        &'r self,
+        haystack: &'h [u8],
+    ) -> bytes::OverlappingMatches<'r, 'h> {
+        bytes::OverlappingMatches {
+            regex: self,
+            haystack,
+            overlaps: Overlaps::new(&self.program, self.config),
+        }
+    }
+}
+
+// Where the pass over the haystack is and the matches it found but didn't give out yet
+pub(super) struct Overlaps {
+    vm: Overlapping,
+    semantics: Semantics,
+    pub(super) every_end: bool,
+    // the match of every start that is done but comes after one that isn't yet, by the start
+    done: BTreeMap<usize, usize>,
+    ready: VecDeque<Range<usize>>,
+}
+
+impl Overlaps {
+    fn new(program: &Program, config: Config) -> Self {
+        Overlaps {
+            vm: Overlapping::new(program, config.anchored),
+            semantics: config.semantics,
+            every_end: false,
+            done: BTreeMap::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    pub(super) fn next(&mut self, program: &Program, haystack: &[u8]) -> Option<Range<usize>> {
+        while self.ready.is_empty() && !self.vm.is_done(haystack) {
+            let step = self
+                .vm
+                .step(program, self.semantics, haystack, self.every_end);
+            let matched = step.matched.into_iter().map(|(start, end)| start..end);
+            if self.every_end {
+                self.ready.extend(matched);
+                continue;
+            }
+            self.done
+                .extend(matched.map(|found| (found.start, found.end)));
+            // the starts before the earliest one with threads are done, in their order
+            while let Some(entry) = self.done.first_entry() {
+                if step.live.is_some_and(|live| live < *entry.key()) {
+                    break;
+                }
+                let (start, end) = entry.remove_entry();
+                self.ready.push_back(start..end);
+            }
+        }
+        self.ready.pop_front()
+    }
+}
+
+pub struct OverlappingMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    overlaps: Overlaps,
+}
+
+impl OverlappingMatches<'_, '_> {
+    // every match of every start instead of the one a search from there finds
+    pub fn every_end(mut self, yes: bool) -> Self {
+        self.overlaps.every_end = yes;
+        self
+    }
+}
+
+impl<'h> Iterator for OverlappingMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        let haystack = self.haystack;
+        let found = self
+            .overlaps
+            .next(&self.regex.program, haystack.as_bytes())?;
+        Some(Match {
+            haystack,
+            start: found.start,
+            end: found.end,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::matcher::pikevm::{self, SearchKind};
+    use crate::RegexBuilder;
+
+    fn overlapping(re: &Regex, haystack: &str, every_end: bool) -> Vec<Range<usize>> {
+        re.find_overlapping_iter(haystack)
+            .every_end(every_end)
+            .map(|m| m.range())
+            .collect()
+    }
+
+    #[test]
+    fn matches_at_every_start() {
+        let re = Regex::new("ATA").unwrap();
+        assert_eq!(overlapping(&re, "ATATA", false), [0..3, 2..5]);
+        assert_eq!(overlapping(&re, "ATATATA", true), [0..3, 2..5, 4..7]);
+        assert_eq!(re.find_iter("ATATA").count(), 1);
+
+        // the starts inside of a match from an earlier one have their own
+        let re = Regex::new("[a-z]+").unwrap();
+        assert_eq!(overlapping(&re, "ab cd", false), [0..2, 1..2, 3..5, 4..5]);
+        assert_eq!(overlapping(&re, "ab c", true), [0..1, 0..2, 1..2, 3..4]);
+        let re = Regex::new("(ab|b)c").unwrap();
+        assert_eq!(overlapping(&re, "abcbc", false), [0..3, 1..3, 3..5]);
+        let re = Regex::new("a*b").unwrap();
+        assert_eq!(overlapping(&re, "aab", false), [0..3, 1..3, 2..3]);
+
+        // every end of every way to match, in order
+        let re = Regex::new("(a|ab)(c|bcd)?").unwrap();
+        assert_eq!(overlapping(&re, "abcd", true), [0..1, 0..2, 0..3, 0..4]);
+        assert_eq!(
+            re.find_overlapping_iter("xabcd").next().unwrap().range(),
+            1..5
+        );
+
+        // empty matches only between chars
+        let re = Regex::new("é*").unwrap();
+        assert_eq!(overlapping(&re, "aé", false), [0..0, 1..3, 3..3]);
+        assert_eq!(overlapping(&re, "aé", true), [0..0, 1..1, 1..3, 3..3]);
+        let re = RegexBuilder::new("A").anchored(true).build().unwrap();
+        let found = overlapping(&re, "AAA", false);
+        assert_eq!((found.len(), found.first()), (1, Some(&(0..1))));
+    }
+
+    #[test]
+    fn the_match_of_a_search_from_every_start() {
+        for (pattern, haystack) in [
+            ("a+", "aaa"),
+            ("(a|ab)(c|bcd)?", "abcbcdabcd"),
+            ("(ab|b)c", "abcbc abc"),
+            ("[0-9]+(\\.[0-9]+)?", "1.25 3."),
+            ("[a-z]+$", "ab cd"),
+            ("(é|x*)", "aéé"),
+        ] {
+            for semantics in [Semantics::LeftmostFirst, Semantics::LeftmostLongest] {
+                let re = RegexBuilder::new(pattern)
+                    .semantics(semantics)
+                    .build()
+                    .unwrap();
+                let expected: Vec<_> = (0..=haystack.len())
+                    .filter(|&i| haystack.is_char_boundary(i))
+                    .filter_map(|i| {
+                        let bytes = haystack.as_bytes();
+                        let slots =
+                            pikevm::search(&re.program, semantics, bytes, i, SearchKind::Prefix)?;
+                        Some(slots[0]?..slots[1]?)
+                    })
+                    .collect();
+                assert_eq!(
+                    overlapping(&re, haystack, false),
+                    expected,
+                    "{pattern} {haystack} {semantics:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_bytes() {
+        let re = bytes::Regex::new(r"\xff.").unwrap();
+        let found: Vec<_> = re
+            .find_overlapping_iter(b"\xff\xff\xffa")
+            .map(|m| m.range())
+            .collect();
+        assert_eq!(found, [0..2, 1..3, 2..4]);
+        let re = bytes::Regex::new("x*").unwrap();
+        assert_eq!(re.find_overlapping_iter(b"\xff").count(), 2);
+    }
+}
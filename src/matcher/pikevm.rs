@@ -0,0 +1,644 @@
+// A pike VM, it runs all the threads of the NFA in lock step so the time is linear in the
+// length of the haystack no matter how the pattern looks like.
+
+use super::compile::{Inst, Look, Program};
+use super::haystack::Haystack;
+use super::{LineTerminator, Semantics};
+
+pub(crate) type Slots = Vec<Option<usize>>;
+
+// What the search is looking for, the engine knows so it can stop as soon as possible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchKind {
+    // a match anywhere after the start
+    Unanchored,
+    // a match that starts right at the start
+    Prefix,
+    // a match from the start to the end of the haystack
+    Full,
+}
+
+impl SearchKind {
+    fn starts_at(self, pos: usize, start: usize) -> bool {
+        self == SearchKind::Unanchored || pos == start
+    }
+
+    // `byte` is the one at the position, there is none at the end
+    fn ends_at(self, byte: Option<u8>) -> bool {
+        self != SearchKind::Full || byte.is_none()
+    }
+}
+
+// Threads sorted by priority, a pc is in the list at most once
+struct Threads {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+    slots: Vec<Slots>,
+}
+
+impl Threads {
+    fn new(program: &Program) -> Self {
+        Threads {
+            dense: Vec::with_capacity(program.insts.len()),
+            sparse: vec![0; program.insts.len()],
+            slots: vec![Vec::new(); program.insts.len()],
+        }
+    }
+
+    fn contains(&self, pc: usize) -> bool {
+        let i = self.sparse[pc];
+        i < self.dense.len() && self.dense[i] == pc
+    }
+
+    fn insert(&mut self, pc: usize) {
+        self.sparse[pc] = self.dense.len();
+        self.dense.push(pc);
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+// How far a search over a haystack that more can follow after got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Progress {
+    // nothing after the end of the haystack can change the match
+    Done(Option<Slots>),
+    // threads were still going at the end, `matched` is the best match till there and
+    // `earliest` where the earliest of the threads started
+    Pending {
+        matched: Option<Slots>,
+        earliest: usize,
+    },
+}
+
+// Everything before `start` is still seen by the assertions
+pub(crate) fn search<H: Haystack + ?Sized>(
+    program: &Program,
+    semantics: Semantics,
+    haystack: &H,
+    start: usize,
+    kind: SearchKind,
+) -> Option<Slots> {
+    search_before(program, semantics, haystack, start, haystack.len(), kind)
+}
+
+// the matches end by `end`, the assertions still see the bytes after it
+pub(crate) fn search_before<H: Haystack + ?Sized>(
+    program: &Program,
+    semantics: Semantics,
+    haystack: &H,
+    start: usize,
+    end: usize,
+    kind: SearchKind,
+) -> Option<Slots> {
+    match run(program, semantics, haystack, false, start, end, kind) {
+        Progress::Done(matched) => matched,
+        Progress::Pending { .. } => unreachable!("the whole haystack is there"),
+    }
+}
+
+// for a buffer of a stream, the search stops at its end and tells what it is waiting for
+pub(crate) fn search_partial<H: Haystack + ?Sized>(
+    program: &Program,
+    semantics: Semantics,
+    haystack: &H,
+    start: usize,
+    kind: SearchKind,
+) -> Progress {
+    run(
+        program,
+        semantics,
+        haystack,
+        true,
+        start,
+        haystack.len(),
+        kind,
+    )
+}
+
+fn run<H: Haystack + ?Sized>(
+    program: &Program,
+    semantics: Semantics,
+    haystack: &H,
+    partial: bool,
+    start: usize,
+    end: usize,
+    kind: SearchKind,
+) -> Progress {
+    let mut current = Threads::new(program);
+    let mut next = Threads::new(program);
+    let mut matched: Option<Slots> = None;
+    let mut pos = start;
+    let mut before = haystack.rev_cursor(start).peek();
+    let mut cursor = haystack.cursor(start);
+    let mut byte = cursor.next();
+
+    loop {
+        let here = Around {
+            before,
+            after: byte,
+            partial,
+        };
+        // a new thread for every start position, it has the lowest priority of them all
+        let starting = matched.is_none() && kind.starts_at(pos, start);
+        if starting && can_start(program, byte) {
+            add(
+                program,
+                semantics,
+                &mut current,
+                0,
+                pos,
+                vec![None; program.slots],
+                here,
+            );
+        }
+        if current.dense.is_empty() && !starting {
+            break;
+        }
+
+        let there = Around {
+            before: byte,
+            after: cursor.peek(),
+            partial,
+        };
+        // at the end of a partial haystack every thread that is left waits for more
+        let mut earliest = starting.then_some(pos);
+        // nothing from `end` on is part of a match
+        let taken = byte.filter(|_| pos < end);
+        for i in 0..current.dense.len() {
+            let pc = current.dense[i];
+            match &program.insts[pc] {
+                // for a full match the threads that stop early are just dropped
+                Inst::Match(_) if !kind.ends_at(taken) => {}
+                Inst::Match(_) => {
+                    let slots = &current.slots[pc];
+                    if semantics == Semantics::LeftmostFirst {
+                        matched = Some(slots.clone());
+                        // the rest of the threads have a lower priority
+                        break;
+                    }
+                    if matched
+                        .as_ref()
+                        .is_none_or(|best| posix_better(slots, best))
+                    {
+                        matched = Some(slots.clone());
+                    }
+                }
+                inst => match taken {
+                    Some(byte) if inst.matches(byte) => {
+                        let slots = current.slots[pc].clone();
+                        add(program, semantics, &mut next, pc + 1, pos + 1, slots, there);
+                    }
+                    Some(_) => {}
+                    None => {
+                        let started = current.slots[pc][0].unwrap_or(pos);
+                        earliest = Some(earliest.map_or(started, |e| e.min(started)));
+                    }
+                },
+            }
+        }
+
+        if taken.is_none() {
+            if let Some(earliest) = earliest.filter(|_| partial) {
+                return Progress::Pending { matched, earliest };
+            }
+            break;
+        }
+        pos += 1;
+        before = byte;
+        byte = cursor.next();
+        std::mem::swap(&mut current, &mut next);
+        next.clear();
+    }
+
+    Progress::Done(matched)
+}
+
+// Runs every pattern of a set at once and notes which of them match somewhere, there are no
+// priorities so nothing is cut off. With `any` it stops at the first match of any pattern.
+pub(crate) fn search_set<H: Haystack + ?Sized>(
+    program: &Program,
+    haystack: &H,
+    start: usize,
+    kind: SearchKind,
+    patterns: usize,
+    any: bool,
+) -> Vec<bool> {
+    let mut current = Threads::new(program);
+    let mut next = Threads::new(program);
+    let mut matched = vec![false; patterns];
+    let mut left = patterns;
+    let mut pos = start;
+    let mut before = haystack.rev_cursor(start).peek();
+    let mut cursor = haystack.cursor(start);
+    let mut byte = cursor.next();
+
+    loop {
+        if kind.starts_at(pos, start) {
+            if can_start(program, byte) {
+                let here = Around {
+                    before,
+                    after: byte,
+                    partial: false,
+                };
+                add(
+                    program,
+                    Semantics::LeftmostFirst,
+                    &mut current,
+                    0,
+                    pos,
+                    Vec::new(),
+                    here,
+                );
+            }
+        } else if current.dense.is_empty() {
+            break;
+        }
+
+        let there = Around {
+            before: byte,
+            after: cursor.peek(),
+            partial: false,
+        };
+        for i in 0..current.dense.len() {
+            let pc = current.dense[i];
+            match &program.insts[pc] {
+                Inst::Match(id) => {
+                    if !matched[*id] && kind.ends_at(byte) {
+                        matched[*id] = true;
+                        left -= 1;
+                    }
+                }
+                inst => {
+                    if byte.is_some_and(|byte| inst.matches(byte)) {
+                        let slots = std::mem::take(&mut current.slots[pc]);
+                        add(
+                            program,
+                            Semantics::LeftmostFirst,
+                            &mut next,
+                            pc + 1,
+                            pos + 1,
+                            slots,
+                            there,
+                        );
+                    }
+                }
+            }
+        }
+        if left == 0 || (any && left < patterns) {
+            break;
+        }
+
+        if byte.is_none() {
+            break;
+        }
+        pos += 1;
+        before = byte;
+        byte = cursor.next();
+        std::mem::swap(&mut current, &mut next);
+        next.clear();
+    }
+
+    matched
+}
+
+// One pass for the overlapping matches. Every position starts its own threads and those
+// of every start run on their own like a prefix search from there, the same instruction can
+// be in the list of more than one start. The time is the length of the haystack times the
+// starts that are still going, for a motif of a fixed length that is at most its length.
+pub(crate) struct Overlapping {
+    // the starts that still have threads, in order
+    live: Vec<Start>,
+    kept: Vec<Start>,
+    next: Threads,
+    // the lists of the starts that are done, to be used again
+    spare: Vec<Threads>,
+    pos: usize,
+    anchored: bool,
+}
+
+struct Start {
+    at: usize,
+    threads: Threads,
+    // where the best match from there ends so far
+    end: Option<usize>,
+}
+
+// What a step over one position found
+pub(crate) struct Step {
+    // with `every_end` the matches that end at the position, otherwise the starts that are
+    // done with their match, both in the order of the starts
+    pub(crate) matched: Vec<(usize, usize)>,
+    // the earliest start that still has threads
+    pub(crate) live: Option<usize>,
+}
+
+impl Overlapping {
+    pub(crate) fn new(program: &Program, anchored: bool) -> Self {
+        Overlapping {
+            live: Vec::new(),
+            kept: Vec::new(),
+            next: Threads::new(program),
+            spare: Vec::new(),
+            pos: 0,
+            anchored,
+        }
+    }
+
+    pub(crate) fn is_done(&self, haystack: &[u8]) -> bool {
+        self.pos > haystack.len()
+    }
+
+    pub(crate) fn step(
+        &mut self,
+        program: &Program,
+        semantics: Semantics,
+        haystack: &[u8],
+        every_end: bool,
+    ) -> Step {
+        let pos = self.pos;
+        let byte = haystack.get(pos).copied();
+        let before = pos.checked_sub(1).map(|i| haystack[i]);
+        if (!self.anchored || pos == 0) && can_start(program, byte) {
+            let here = Around {
+                before,
+                after: byte,
+                partial: false,
+            };
+            let mut threads = self.spare.pop().unwrap_or_else(|| Threads::new(program));
+            // only the start is needed and that is known
+            add(program, semantics, &mut threads, 0, pos, Vec::new(), here);
+            self.live.push(Start {
+                at: pos,
+                threads,
+                end: None,
+            });
+        }
+
+        let there = Around {
+            before: byte,
+            after: haystack.get(pos + 1).copied(),
+            partial: false,
+        };
+        let mut matched = Vec::new();
+        for start in &mut self.live {
+            let current = &mut start.threads;
+            for i in 0..current.dense.len() {
+                let pc = current.dense[i];
+                match &program.insts[pc] {
+                    Inst::Match(_) if every_end => {
+                        if start.end != Some(pos) {
+                            start.end = Some(pos);
+                            matched.push((start.at, pos));
+                        }
+                    }
+                    Inst::Match(_) => {
+                        // a later match of the same start is always the longer one
+                        start.end = Some(pos);
+                        if semantics == Semantics::LeftmostFirst {
+                            // the rest of the threads have a lower priority
+                            break;
+                        }
+                    }
+                    inst => {
+                        if byte.is_some_and(|byte| inst.matches(byte)) {
+                            add(
+                                program,
+                                semantics,
+                                &mut self.next,
+                                pc + 1,
+                                pos + 1,
+                                Vec::new(),
+                                there,
+                            );
+                        }
+                    }
+                }
+            }
+            std::mem::swap(current, &mut self.next);
+            self.next.clear();
+        }
+
+        self.pos += 1;
+        for start in self.live.drain(..) {
+            if !start.threads.dense.is_empty() {
+                self.kept.push(start);
+                continue;
+            }
+            if let (false, Some(end)) = (every_end, start.end) {
+                matched.push((start.at, end));
+            }
+            self.spare.push(start.threads);
+        }
+        std::mem::swap(&mut self.live, &mut self.kept);
+        Step {
+            matched,
+            live: self.live.first().map(|start| start.at),
+        }
+    }
+}
+
+// Runs a reversed program back from `end` and gives the span of the match that ends last
+// and of those the longest one, without the groups. `anchored` only takes matches that start
+// at the start of the haystack.
+pub(crate) fn search_rev<H: Haystack + ?Sized>(
+    program: &Program,
+    haystack: &H,
+    end: usize,
+    anchored: bool,
+) -> Option<(usize, usize)> {
+    let mut current = Threads::new(program);
+    let mut next = Threads::new(program);
+    let mut best: Option<(usize, usize)> = None;
+    let mut pos = end;
+    let mut after = haystack.cursor(end).peek();
+    let mut cursor = haystack.rev_cursor(end);
+    // the byte before the position, the one the threads take next
+    let mut byte = cursor.next();
+
+    loop {
+        let here = Around {
+            before: byte,
+            after,
+            partial: false,
+        };
+        // once there is a match a thread that starts further back can't end any later
+        let starting = best.is_none();
+        if starting && can_start(program, after) {
+            add(
+                program,
+                Semantics::LeftmostFirst,
+                &mut current,
+                0,
+                pos,
+                vec![None; program.slots],
+                here,
+            );
+        }
+        if current.dense.is_empty() && !starting {
+            break;
+        }
+
+        let there = Around {
+            before: cursor.peek(),
+            after: byte,
+            partial: false,
+        };
+        for i in 0..current.dense.len() {
+            let pc = current.dense[i];
+            // the threads are in the order they started in so the ones that end later come
+            // first, the others can't do better than the best match anymore
+            let ends = current.slots[pc][1].unwrap_or(pos);
+            if best.is_some_and(|(_, best)| ends < best) {
+                continue;
+            }
+            match &program.insts[pc] {
+                Inst::Match(_) => {
+                    if can_start(program, after) && (!anchored || pos == 0) {
+                        best = Some((pos, ends));
+                    }
+                }
+                inst => {
+                    if byte.is_some_and(|byte| inst.matches(byte)) {
+                        let slots = std::mem::take(&mut current.slots[pc]);
+                        add(
+                            program,
+                            Semantics::LeftmostFirst,
+                            &mut next,
+                            pc + 1,
+                            pos - 1,
+                            slots,
+                            there,
+                        );
+                    }
+                }
+            }
+        }
+
+        if byte.is_none() {
+            break;
+        }
+        pos -= 1;
+        after = byte;
+        byte = cursor.next();
+        std::mem::swap(&mut current, &mut next);
+        next.clear();
+    }
+
+    best
+}
+
+// a `&str` is only searched from char boundaries so no match splits a char
+fn can_start(program: &Program, byte: Option<u8>) -> bool {
+    !program.utf8 || byte.is_none_or(|byte| byte & 0xc0 != 0x80)
+}
+
+// What the assertions can see at a position, the byte on either side of it. When the
+// haystack is only a part with more to come what is after its end isn't known yet.
+#[derive(Debug, Clone, Copy)]
+struct Around {
+    before: Option<u8>,
+    after: Option<u8>,
+    partial: bool,
+}
+
+// Follows all the empty transitions from `pc` and adds the threads that are waiting on a
+// byte (or a match) to the list
+fn add(
+    program: &Program,
+    semantics: Semantics,
+    threads: &mut Threads,
+    pc: usize,
+    pos: usize,
+    slots: Slots,
+    around: Around,
+) {
+    let mut stack = vec![(pc, slots)];
+
+    while let Some((pc, mut slots)) = stack.pop() {
+        if threads.contains(pc) {
+            // in leftmost first the thread that got here first has the higher priority,
+            // for POSIX the one with the better submatches wins and is followed again
+            if semantics == Semantics::LeftmostFirst || !posix_better(&slots, &threads.slots[pc]) {
+                continue;
+            }
+        } else {
+            threads.insert(pc);
+        }
+
+        match &program.insts[pc] {
+            Inst::Jump(to) => stack.push((*to, slots.clone())),
+            Inst::Split(first, second) => {
+                stack.push((*second, slots.clone()));
+                stack.push((*first, slots.clone()));
+            }
+            // the threads of a set have no slots, it never reports the groups
+            Inst::Save(_) if slots.is_empty() => stack.push((pc + 1, Vec::new())),
+            Inst::Save(slot) => {
+                let mut saved = slots.clone();
+                saved[*slot] = Some(pos);
+                stack.push((pc + 1, saved));
+            }
+            Inst::Assert(look) if is_satisfied(*look, around) => {
+                stack.push((pc + 1, slots.clone()));
+            }
+            _ => {}
+        }
+        threads.slots[pc] = std::mem::take(&mut slots);
+    }
+}
+
+// the line terminators are all ASCII so looking at a single byte is enough. At the end of a
+// partial haystack nothing that looks ahead holds, the thread waits at the assertion till the
+// search goes on with more.
+fn is_satisfied(look: Look, around: Around) -> bool {
+    let before = around.before.map(char::from);
+    let after = around.after.map(char::from);
+    if around.partial && after.is_none() && looks_ahead(look, before) {
+        return false;
+    }
+    match look {
+        Look::Start => before.is_none(),
+        Look::End => after.is_none(),
+        // a line never starts or ends between the '\r' and the '\n' of a CRLF
+        Look::StartLine(LineTerminator::CrLf) => match before {
+            None | Some('\n') => true,
+            Some('\r') => after != Some('\n'),
+            _ => false,
+        },
+        Look::EndLine(LineTerminator::CrLf) => match after {
+            None | Some('\r') => true,
+            Some('\n') => before != Some('\r'),
+            _ => false,
+        },
+        Look::StartLine(terminator) => before.is_none_or(|c| terminator.chars().contains(&c)),
+        Look::EndLine(terminator) => after.is_none_or(|c| terminator.chars().contains(&c)),
+    }
+}
+
+fn looks_ahead(look: Look, before: Option<char>) -> bool {
+    match look {
+        Look::Start => false,
+        Look::StartLine(LineTerminator::CrLf) => before == Some('\r'),
+        Look::StartLine(_) => false,
+        Look::End | Look::EndLine(_) => true,
+    }
+}
+
+// POSIX wants the leftmost and then the longest match, after that every group in order
+// should again be leftmost and then longest
+pub(crate) fn posix_better(new: &Slots, old: &Slots) -> bool {
+    for (i, (new, old)) in new.iter().zip(old.iter()).enumerate() {
+        let is_start = i % 2 == 0;
+        match (new, old) {
+            (Some(new), Some(old)) if new == old => continue,
+            (Some(new), Some(old)) => return if is_start { new < old } else { new > old },
+            (Some(_), None) => return true,
+            (None, Some(_)) => return false,
+            (None, None) => continue,
+        }
+    }
+    false
+}
@@ -0,0 +1,162 @@
+// Rewriting the matches of a regex, either with a template like `$1-${name}` or with a
+// closure that gets the captures of every match.
+
+use std::borrow::Cow;
+
+use super::{Captures, Regex};
+
+// Writes the replacement for a single match to the end of `dst`
+pub trait Replacer {
+    fn replace_append(&mut self, captures: &Captures<'_>, dst: &mut String);
+}
+
+// a template, see `Captures::expand`
+impl Replacer for &str {
+    fn replace_append(&mut self, captures: &Captures<'_>, dst: &mut String) {
+        captures.expand(self, dst);
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, captures: &Captures<'_>, dst: &mut String) {
+        captures.expand(self, dst);
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, captures: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self(captures).as_ref());
+    }
+}
+
+impl Captures<'_> {
+    // `$0` and `$1` are replaced by the groups with that number and `${name}` or `${1}` by
+    // the group with that name or number, `$$` is a single '$'. A group that didn't take
+    // part in the match is replaced by nothing and a '$' that starts none of these stays.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (group, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], &braced[end + 1..]),
+                    None => ("", rest),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+            if group.is_empty() {
+                dst.push('$');
+                continue;
+            }
+
+            let found = match group.parse::<usize>() {
+                Ok(i) => self.get(i),
+                Err(_) => self.name(group),
+            };
+            if let Some(found) = found {
+                dst.push_str(found.as_str());
+            }
+            rest = after;
+        }
+        dst.push_str(rest);
+    }
+}
+
+impl Regex {
+    // only the leftmost match is replaced
+    pub fn replace<'h, R: Replacer>(&self, haystack: &'h str, replacer: R) -> Cow<'h, str> {
+        self.replacen(haystack, 1, replacer)
+    }
+
+    pub fn replace_all<'h, R: Replacer>(&self, haystack: &'h str, replacer: R) -> Cow<'h, str> {
+        self.replacen(haystack, 0, replacer)
+    }
+
+    // replaces at most `limit` matches, all of them when it is 0. Without any match the
+    // haystack is given back as it is.
+    pub fn replacen<'h, R: Replacer>(
+        &self,
+        haystack: &'h str,
+        limit: usize,
+        mut replacer: R,
+    ) -> Cow<'h, str> {
+        let mut replaced: Option<String> = None;
+        let mut last_end = 0;
+
+        for (i, captures) in self.captures_iter(haystack).enumerate() {
+            if limit > 0 && i >= limit {
+                break;
+            }
+            let found = captures.get(0).unwrap();
+            let dst = replaced.get_or_insert_with(|| String::with_capacity(haystack.len()));
+            dst.push_str(&haystack[last_end..found.start()]);
+            replacer.replace_append(&captures, dst);
+            last_end = found.end();
+        }
+
+        match replaced {
+            Some(mut dst) => {
+                dst.push_str(&haystack[last_end..]);
+                Cow::Owned(dst)
+            }
+            None => Cow::Borrowed(haystack),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let re = Regex::new(r"(?P<year>[0-9]{4})-(?<month>[0-9]{2})").unwrap();
+        let names: Vec<_> = re.capture_names().collect();
+        assert_eq!(names, [None, Some("year"), Some("month")]);
+        assert_eq!(
+            re.replace_all("2022-05 and 2023-11", "${month}/$1 ($0)"),
+            "05/2022 (2022-05) and 11/2023 (2023-11)"
+        );
+        assert_eq!(re.replace("2022-05", "$$1 ${3} $x ${year"), "$1  $x ${year");
+        assert_eq!(re.replace("2022-05", "${1}0 $10"), "20220 ");
+
+        let re = Regex::new("a(x)?").unwrap();
+        assert_eq!(re.replace_all("aax", "[$1]"), "[][x]");
+    }
+
+    #[test]
+    fn closures_and_limits() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let doubled = re.replace_all("1 2 30", |caps: &Captures<'_>| {
+            (caps[0].parse::<u32>().unwrap() * 2).to_string()
+        });
+        assert_eq!(doubled, "2 4 60");
+        assert_eq!(re.replacen("1 2 3 4", 2, "#"), "# # 3 4");
+        assert_eq!(re.replace_all("a1b", String::from("<$0>")), "a<1>b");
+
+        assert!(matches!(re.replace_all("none", "x"), Cow::Borrowed("none")));
+        assert!(matches!(re.replace("7", "x"), Cow::Owned(_)));
+
+        // empty matches are replaced between every character
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(re.replace_all("abc", "-"), "-a-b-c-");
+    }
+}
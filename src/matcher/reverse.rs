@@ -0,0 +1,302 @@
+// Searching from the end of the haystack back, for the last match in a huge log without going
+// over all the ones before it. A reversed program finds where the last match starts, it is
+// the longest of the matches that end last. From there the forward program gives the span
+// and that is the match a forward search starting there finds.
+//
+// Most regexes never search back so the reversed program is only built the first time it is
+// needed. The ast isn't kept around, the pattern is parsed again for it.
+
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use super::bytes;
+use super::compile::{self, Program};
+use super::pikevm::{self, SearchKind};
+use super::{Config, Match, Regex};
+use crate::parser::{self, Options};
+
+impl Regex {
+    pub fn rfind<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        let bytes = haystack.as_bytes();
+        let found = rfind(
+            &self.program,
+            self.reversed(),
+            self.config,
+            bytes,
+            bytes.len(),
+        )?;
+        Some(Match {
+            haystack,
+            start: found.start,
+            end: found.end,
+        })
+    }
+
+    // The matches from the last one back. Where matches could overlap they can be others
+    // than the ones of `find_iter`, it takes the first of them and this one the last: `aa`
+    // finds 1..3 in "aaa" going back and 0..2 going forward.
+    pub fn rfind_iter<'r, 'h>(&'r self, haystack: &'h str) -> RMatches<'r, 'h> {
+        RMatches {
+            regex: self,
+            haystack,
+            back: Backward::new(haystack.len()),
+        }
+    }
+
+    fn reversed(&self) -> &Program {
+        reversed(&self.reverse, &self.pattern, self.options, self.config)
+    }
+}
+
+impl bytes::Regex {
+    pub fn rfind<'h>(&self, haystack: &'h [u8]) -> Option<bytes::Match<'h>> {
+        let found = rfind(
+            &self.program,
+            self.reversed(),
+            self.config,
+            haystack,
+            haystack.len(),
+        )?;
+        Some(bytes::Match {
+            haystack,
+            start: found.start,
+            end: found.end,
+        })
+    }
+
+    pub fn rfind_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> bytes::RMatches<'r, 'h> {
+        bytes::RMatches {
+            regex: self,
+            haystack,
+            back: Backward::new(haystack.len()),
+        }
+    }
+
+    pub(super) fn reversed(&self) -> &Program {
+        reversed(&self.reverse, self.as_str(), self.options, self.config)
+    }
+}
+
+// The pattern already parsed and compiled going forward so neither can fail now. The size
+// limit was checked on the forward program, the reversed one is just as big.
+fn reversed<'r>(
+    reverse: &'r OnceLock<Program>,
+    pattern: &str,
+    options: Options,
+    config: Config,
+) -> &'r Program {
+    reverse.get_or_init(|| {
+        let expression =
+            parser::process_with(pattern, &options).expect("the pattern was parsed before");
+        let config = Config {
+            size_limit: usize::MAX,
+            ..config
+        };
+        compile::compile_reverse(&expression, &config).expect("there is no size limit")
+    })
+}
+
+// The last match that ends by `end`. With leftmost-first the match from the start the
+// reversed program found can stop early, `(a|abc|c)` only takes the `a` of "abc", so the
+// search goes on forward from there and the last of those matches is the one.
+pub(super) fn rfind(
+    program: &Program,
+    reverse: &Program,
+    config: Config,
+    haystack: &[u8],
+    end: usize,
+) -> Option<Range<usize>> {
+    let (start, _) = pikevm::search_rev(reverse, haystack, end, config.anchored)?;
+    let search = |start, kind| {
+        let slots = pikevm::search_before(program, config.semantics, haystack, start, end, kind)?;
+        Some(slots[0]?..slots[1]?)
+    };
+    let mut found = search(start, SearchKind::Prefix)?;
+    let mut pos = found.end;
+    // anchored there is only the match from the start
+    while !config.anchored && found.end < end && pos <= end {
+        let Some(next) = search(pos, SearchKind::Unanchored) else {
+            break;
+        };
+        // an empty match right where the last one ended is not a new match, like in
+        // `find_iter` an empty match goes on after the next byte
+        pos = if next.is_empty() {
+            next.end + 1
+        } else {
+            next.end
+        };
+        if !(next.is_empty() && next.end == found.end) {
+            found = next;
+        }
+    }
+    Some(found)
+}
+
+// How far an iteration from the back got, the next match ends by `end`
+pub(super) struct Backward {
+    end: usize,
+    last_start: Option<usize>,
+    done: bool,
+}
+
+impl Backward {
+    pub(super) fn new(end: usize) -> Self {
+        Backward {
+            end,
+            last_start: None,
+            done: false,
+        }
+    }
+
+    // `find` gives the last match that ends by a position and `back` the position before
+    // one, a char or a byte back
+    pub(super) fn next(
+        &mut self,
+        find: impl Fn(usize) -> Option<Range<usize>>,
+        back: impl Fn(usize) -> usize,
+    ) -> Option<Range<usize>> {
+        while !self.done {
+            let Some(found) = find(self.end) else {
+                break;
+            };
+            // an empty match right where the last one started is not a new match
+            if found.is_empty() && Some(found.end) == self.last_start {
+                if self.end == 0 {
+                    break;
+                }
+                self.end = back(self.end);
+                continue;
+            }
+            self.end = found.start;
+            self.last_start = Some(found.start);
+            return Some(found);
+        }
+        self.done = true;
+        None
+    }
+}
+
+pub struct RMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    back: Backward,
+}
+
+impl<'h> Iterator for RMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        let (regex, haystack) = (self.regex, self.haystack);
+        let reverse = regex.reversed();
+        let found = self.back.next(
+            |end| {
+                rfind(
+                    &regex.program,
+                    reverse,
+                    regex.config,
+                    haystack.as_bytes(),
+                    end,
+                )
+            },
+            |end| previous_char_boundary(haystack, end),
+        )?;
+        Some(Match {
+            haystack,
+            start: found.start,
+            end: found.end,
+        })
+    }
+}
+
+fn previous_char_boundary(haystack: &str, pos: usize) -> usize {
+    pos - haystack[..pos]
+        .chars()
+        .next_back()
+        .map_or(1, char::len_utf8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RegexBuilder;
+
+    fn backward(re: &Regex, haystack: &str) -> Vec<Range<usize>> {
+        re.rfind_iter(haystack).map(|m| m.range()).collect()
+    }
+
+    #[test]
+    fn last_match() {
+        let re = Regex::new("[a-z]+").unwrap();
+        assert_eq!(re.rfind("ab cd 12").unwrap().range(), 3..5);
+        assert_eq!(re.rfind("12"), None);
+
+        // the span is the one a forward search from its start gives
+        let re = Regex::new("(a|ab)(c|bcd)?").unwrap();
+        assert_eq!(re.rfind("xabcd").unwrap().range(), 1..5);
+        assert_eq!(
+            Regex::new("(a|ab)").unwrap().rfind("xab").unwrap().range(),
+            1..2
+        );
+
+        // groups, chars and assertions read back to front
+        let re = Regex::new("(?<k>[a-zé]+)=(?<v>[0-9]+)$").unwrap();
+        assert_eq!(re.rfind("a=1 clé=42").unwrap().as_str(), "clé=42");
+        let re = RegexBuilder::new("^é.$").multi_line(true).build().unwrap();
+        assert_eq!(re.rfind("éa\néb\nxéc").unwrap().range(), 4..7);
+        let re = RegexBuilder::new("b").anchored(true).build().unwrap();
+        assert_eq!(re.rfind("bab"), re.find("bab"));
+    }
+
+    #[test]
+    fn matches_from_the_back() {
+        for (pattern, haystack) in [
+            ("[a-z]+", "ab cd e"),
+            ("a*", "baaab"),
+            ("x*", "héllo"),
+            ("(ab|b)c", "abcbc abc"),
+            ("[0-9]+$", "12\n34"),
+            ("(é|)", "aéé"),
+            ("(a|abc|c)", "abc"),
+            ("(a|abc|c)", "abcabc"),
+            ("(a|ab|b)", "ab"),
+        ] {
+            let re = Regex::new(pattern).unwrap();
+            let mut expected: Vec<_> = re.find_iter(haystack).map(|m| m.range()).collect();
+            expected.reverse();
+            assert_eq!(backward(&re, haystack), expected, "{pattern} {haystack}");
+        }
+        assert_eq!(
+            backward(&Regex::new("a*").unwrap(), "baaab"),
+            [5..5, 1..4, 0..0]
+        );
+        assert_eq!(
+            Regex::new("(a|abc|c)")
+                .unwrap()
+                .rfind("abc")
+                .unwrap()
+                .range(),
+            2..3
+        );
+        // overlapping matches are taken from the end
+        let re = Regex::new("aa").unwrap();
+        assert_eq!(re.rfind("aaa").unwrap().range(), 1..3);
+        assert_eq!(re.rfind_iter("aaa").count(), 1);
+        assert_eq!(re.find("aaa").unwrap().range(), 0..2);
+
+        // the reversed program is only there after a search back
+        let re = Regex::new("aa").unwrap();
+        assert!(re.find("aaa").is_some() && re.reverse.get().is_none());
+        assert!(re.rfind("a").is_none() && re.reverse.get().is_some());
+        let re = RegexBuilder::new("a{1000}")
+            .size_limit(1 << 16)
+            .build()
+            .unwrap();
+        assert_eq!(re.rfind(&"a".repeat(1001)).unwrap().range(), 1..1001);
+
+        let re = bytes::Regex::new("x*").unwrap();
+        let found: Vec<_> = re.rfind_iter(b"a\xff").map(|m| m.range()).collect();
+        assert_eq!(found, [2..2, 1..1, 0..0]);
+        let re = bytes::Regex::new(r"\xff[^a]").unwrap();
+        assert_eq!(re.rfind(b"\xff\xfe\xffa\xff\xff").unwrap().range(), 4..6);
+    }
+}
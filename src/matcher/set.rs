@@ -0,0 +1,243 @@
+// Many patterns compiled into one program, a single pass over the haystack tells which of
+// them match somewhere in it. The threads carry no slots since the groups are never reported,
+// `benches/set.rs` compares it with trying the patterns one by one.
+
+use super::compile::{self, Program};
+use super::pikevm::{self, SearchKind};
+use super::{Config, Error};
+use crate::parser::{self, Expression};
+
+#[derive(Debug, Clone)]
+pub struct RegexSet {
+    patterns: Vec<String>,
+    program: Program,
+    anchored: bool,
+}
+
+// Which patterns of the set matched, by their index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetMatches {
+    matched: Vec<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SetMatchesIter {
+    matches: SetMatches,
+    next: usize,
+}
+
+impl RegexSet {
+    pub fn new<I, S>(patterns: I) -> Result<RegexSet, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns: Vec<String> = patterns
+            .into_iter()
+            .map(|pattern| pattern.as_ref().to_string())
+            .collect();
+        let expressions = patterns
+            .iter()
+            .map(|pattern| parser::process(pattern))
+            .collect::<Result<Vec<Expression>, _>>()?;
+
+        Ok(RegexSet {
+            program: compile::compile_set(&expressions, &Config::default())?,
+            patterns,
+            anchored: false,
+        })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    // a pattern only counts when it matches right at the start of the search
+    pub fn anchored(&mut self, yes: bool) -> &mut Self {
+        self.anchored = yes;
+        self
+    }
+
+    // stops at the first match of any of the patterns
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.is_match_at(haystack, 0)
+    }
+
+    pub fn is_match_at(&self, haystack: &str, start: usize) -> bool {
+        !self.is_empty()
+            && self
+                .search(haystack, start, self.kind(), true)
+                .contains(&true)
+    }
+
+    pub fn matches(&self, haystack: &str) -> SetMatches {
+        self.matches_at(haystack, 0)
+    }
+
+    // the text before `start` is not searched but '^' still knows it is there
+    pub fn matches_at(&self, haystack: &str, start: usize) -> SetMatches {
+        self.matches_with(haystack, start, self.kind())
+    }
+
+    // the patterns that match all of the haystack
+    pub fn full_matches(&self, haystack: &str) -> SetMatches {
+        self.matches_with(haystack, 0, SearchKind::Full)
+    }
+
+    // the indices of the patterns that match in increasing order
+    pub fn matches_iter(&self, haystack: &str) -> SetMatchesIter {
+        self.matches(haystack).into_iter()
+    }
+
+    fn kind(&self) -> SearchKind {
+        if self.anchored {
+            SearchKind::Prefix
+        } else {
+            SearchKind::Unanchored
+        }
+    }
+
+    fn matches_with(&self, haystack: &str, start: usize, kind: SearchKind) -> SetMatches {
+        let matched = if self.is_empty() {
+            Vec::new()
+        } else {
+            self.search(haystack, start, kind, false)
+        };
+        SetMatches { matched }
+    }
+
+    fn search(&self, haystack: &str, start: usize, kind: SearchKind, any: bool) -> Vec<bool> {
+        pikevm::search_set(
+            &self.program,
+            haystack.as_bytes(),
+            start,
+            kind,
+            self.len(),
+            any,
+        )
+    }
+}
+
+impl SetMatches {
+    pub fn matched(&self, i: usize) -> bool {
+        self.matched.get(i).copied().unwrap_or(false)
+    }
+
+    pub fn matched_any(&self) -> bool {
+        self.matched.contains(&true)
+    }
+
+    // the number of patterns in the set, matched or not
+    pub fn len(&self) -> usize {
+        self.matched.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matched.is_empty()
+    }
+}
+
+impl IntoIterator for SetMatches {
+    type Item = usize;
+    type IntoIter = SetMatchesIter;
+
+    fn into_iter(self) -> SetMatchesIter {
+        SetMatchesIter {
+            matches: self,
+            next: 0,
+        }
+    }
+}
+
+impl Iterator for SetMatchesIter {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.next < self.matches.len() {
+            let i = self.next;
+            self.next += 1;
+            if self.matches.matched[i] {
+                return Some(i);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matching_many_patterns() {
+        let set = RegexSet::new([
+            r"^ERROR",
+            r"timeout after [0-9]+ms",
+            r"(disk|memory) full",
+            r"^WARN",
+            r"full$",
+        ])
+        .unwrap();
+
+        let line = "ERROR: disk full";
+        assert_eq!(set.matches_iter(line).collect::<Vec<_>>(), [0, 2, 4]);
+        let matches = set.matches("WARN timeout after 30ms");
+        assert!(matches.matched(1) && matches.matched(3) && !matches.matched(0));
+        assert_eq!(matches.len(), 5);
+
+        assert!(set.is_match("memory full!"));
+        assert!(!set.is_match("all good"));
+        assert!(!set.matches("all good").matched_any());
+    }
+
+    #[test]
+    fn edge_cases() {
+        let empty = RegexSet::new(Vec::<&str>::new()).unwrap();
+        assert!(!empty.is_match("a") && empty.matches("a").is_empty());
+
+        let set = RegexSet::new(["", "x*", "^$"]).unwrap();
+        assert_eq!(set.matches_iter("").collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(set.matches_iter("y").collect::<Vec<_>>(), [0, 1]);
+
+        // the groups of every pattern start again at 1
+        let set = RegexSet::new(["(a)(b)", "(c)"]).unwrap();
+        assert_eq!(set.matches_iter("xc").collect::<Vec<_>>(), [1]);
+
+        assert!(RegexSet::new(["a", "(b"]).is_err());
+    }
+
+    #[test]
+    fn anchored_and_offset_searches() {
+        let mut set = RegexSet::new(["[0-9]+", "[a-z]+", "^x"]).unwrap();
+        assert_eq!(
+            set.matches_at("x12ab", 1).into_iter().collect::<Vec<_>>(),
+            [0, 1]
+        );
+
+        set.anchored(true);
+        assert_eq!(
+            set.matches_at("x12ab", 1).into_iter().collect::<Vec<_>>(),
+            [0]
+        );
+        assert_eq!(
+            set.matches_at("x12ab", 0).into_iter().collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert!(!set.is_match_at("x12ab", 5));
+        assert!(set.is_match_at("x12ab", 3));
+
+        let set = RegexSet::new(["[0-9]+", "[0-9a-z]+", "[a-z]*"]).unwrap();
+        let full = |haystack| set.full_matches(haystack).into_iter().collect::<Vec<_>>();
+        assert_eq!(full("12"), [0, 1]);
+        assert_eq!(full("12ab"), [1]);
+        assert_eq!(full(""), [2]);
+    }
+}
@@ -0,0 +1,187 @@
+// Splitting a haystack on the matches of a regex. The pieces are what is between two
+// matches, so an empty match splits between characters and a match at the very start or
+// end gives an empty piece there. The matches are the ones of `find_iter`, an empty match
+// right where the last one ended doesn't count. That is where it differs from python's
+// `re.split`: `x*` splits "axxb" into "", "a", "b", "" and python has another "" between
+// "a" and "b".
+
+use std::collections::VecDeque;
+
+use super::{CaptureMatches, Matches, Regex};
+
+pub struct Split<'r, 'h> {
+    matches: Matches<'r, 'h>,
+    haystack: &'h str,
+    last_end: usize,
+    done: bool,
+}
+
+// at most `limit` pieces, the last one is all of what is left
+pub struct SplitN<'r, 'h> {
+    split: Split<'r, 'h>,
+    limit: usize,
+}
+
+// the pieces with the groups of every delimiter in between them, a group that didn't take
+// part in the match is None
+pub struct SplitCaptures<'r, 'h> {
+    captures: CaptureMatches<'r, 'h>,
+    haystack: &'h str,
+    last_end: usize,
+    done: bool,
+    groups: VecDeque<Option<&'h str>>,
+}
+
+impl Regex {
+    pub fn split<'r, 'h>(&'r self, haystack: &'h str) -> Split<'r, 'h> {
+        Split {
+            matches: self.find_iter(haystack),
+            haystack,
+            last_end: 0,
+            done: false,
+        }
+    }
+
+    pub fn splitn<'r, 'h>(&'r self, haystack: &'h str, limit: usize) -> SplitN<'r, 'h> {
+        SplitN {
+            split: self.split(haystack),
+            limit,
+        }
+    }
+
+    pub fn split_captures<'r, 'h>(&'r self, haystack: &'h str) -> SplitCaptures<'r, 'h> {
+        SplitCaptures {
+            captures: self.captures_iter(haystack),
+            haystack,
+            last_end: 0,
+            done: false,
+            groups: VecDeque::new(),
+        }
+    }
+}
+
+impl<'h> Split<'_, 'h> {
+    fn rest(&mut self) -> Option<&'h str> {
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        Some(&self.haystack[self.last_end..])
+    }
+}
+
+impl<'h> Iterator for Split<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        if self.done {
+            return None;
+        }
+        match self.matches.next() {
+            Some(found) => {
+                let piece = &self.haystack[self.last_end..found.start()];
+                self.last_end = found.end();
+                Some(piece)
+            }
+            None => self.rest(),
+        }
+    }
+}
+
+impl<'h> Iterator for SplitN<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        match self.limit {
+            0 => None,
+            1 => {
+                self.limit = 0;
+                self.split.rest()
+            }
+            _ => {
+                self.limit -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+impl<'h> Iterator for SplitCaptures<'_, 'h> {
+    type Item = Option<&'h str>;
+
+    fn next(&mut self) -> Option<Option<&'h str>> {
+        if let Some(group) = self.groups.pop_front() {
+            return Some(group);
+        }
+        if self.done {
+            return None;
+        }
+
+        match self.captures.next() {
+            Some(captures) => {
+                let found = captures.get(0).unwrap();
+                let piece = &self.haystack[self.last_end..found.start()];
+                self.last_end = found.end();
+                self.groups
+                    .extend((1..captures.len()).map(|i| captures.get(i).map(|m| m.as_str())));
+                Some(Some(piece))
+            }
+            None => {
+                self.done = true;
+                Some(Some(&self.haystack[self.last_end..]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splitting() {
+        let re = Regex::new("[[:space:]]*,[[:space:]]*").unwrap();
+        let fields: Vec<_> = re.split("a , b,c ,, d").collect();
+        assert_eq!(fields, ["a", "b", "c", "", "d"]);
+        assert_eq!(re.split(",a,").collect::<Vec<_>>(), ["", "a", ""]);
+        assert_eq!(re.split("").collect::<Vec<_>>(), [""]);
+
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(re.split("abc").collect::<Vec<_>>(), ["", "a", "b", "c", ""]);
+        // no empty match right after "xx", python would split there again
+        assert_eq!(re.split("axxb").collect::<Vec<_>>(), ["", "a", "b", ""]);
+        assert_eq!(re.split("xxa").collect::<Vec<_>>(), ["", "a", ""]);
+    }
+
+    #[test]
+    fn splitting_n_times() {
+        let re = Regex::new(",").unwrap();
+        assert_eq!(re.splitn("a,b,c,d", 2).collect::<Vec<_>>(), ["a", "b,c,d"]);
+        assert_eq!(re.splitn("a,b", 5).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(re.splitn("a,b", 1).collect::<Vec<_>>(), ["a,b"]);
+        assert_eq!(re.splitn("a,b", 0).count(), 0);
+    }
+
+    #[test]
+    fn splitting_with_groups() {
+        let re = Regex::new("((,)|(;))").unwrap();
+        let pieces: Vec<_> = re.split_captures("a,b;c").collect();
+        assert_eq!(
+            pieces,
+            [
+                Some("a"),
+                Some(","),
+                Some(","),
+                None,
+                Some("b"),
+                Some(";"),
+                None,
+                Some(";"),
+                Some("c")
+            ]
+        );
+        let re = Regex::new("-").unwrap();
+        let pieces: Vec<_> = re.split_captures("a-b").flatten().collect();
+        assert_eq!(pieces, ["a", "b"]);
+    }
+}
@@ -0,0 +1,331 @@
+// Searching a reader without loading all of it, like a huge file or a pipe. The input is
+// read into a buffer of a fixed size, the window, and only the part of it that a match could
+// still start in is kept when it is refilled. The matches come with their offset from the
+// start of the stream.
+//
+// A match can't be longer than the window. When the threads that are still going started
+// more than half a window back the search stops waiting for them: if a match was already
+// found it is reported like the input ended there, so `a+` gives the run of a's in pieces of
+// at most a window, otherwise the starts that are more than half a window back are given up.
+
+use std::io::{self, Read};
+
+use super::bytes::Regex;
+use super::pikevm::{self, Progress, SearchKind, Slots};
+
+const DEFAULT_WINDOW: usize = 64 * 1024;
+const MIN_WINDOW: usize = 16;
+
+pub struct StreamMatches<'r, R> {
+    regex: &'r Regex,
+    reader: R,
+    buffer: Vec<u8>,
+    window: usize,
+    // where the buffer starts in the stream
+    offset: u64,
+    // where the next search starts in the buffer
+    pos: usize,
+    last_end: Option<u64>,
+    eof: bool,
+    done: bool,
+}
+
+// A match copied out of the buffer since that is reused for the rest of the stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamMatch {
+    start: u64,
+    end: u64,
+    bytes: Vec<u8>,
+}
+
+impl Regex {
+    // the matches in everything the reader gives, a `BufRead` can be passed as it is
+    pub fn stream_find_iter<R: Read>(&self, reader: R) -> StreamMatches<'_, R> {
+        StreamMatches {
+            regex: self,
+            reader,
+            buffer: Vec::new(),
+            window: DEFAULT_WINDOW,
+            offset: 0,
+            pos: 0,
+            last_end: None,
+            eof: false,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> StreamMatches<'_, R> {
+    // how many bytes are kept in memory, 64KB by default and never less than 16
+    pub fn window(mut self, bytes: usize) -> Self {
+        self.window = bytes.max(MIN_WINDOW);
+        self
+    }
+
+    // Drops what is before `keep_from` and reads till the window is full or the reader
+    // has nothing more. The byte before the search start stays for the assertions.
+    fn refill(&mut self, keep_from: usize) -> io::Result<()> {
+        let keep_from = keep_from.min(self.buffer.len());
+        self.buffer.drain(..keep_from);
+        self.offset += keep_from as u64;
+        self.pos -= keep_from;
+
+        let mut filled = self.buffer.len();
+        self.buffer.resize(self.window.max(filled + 1), 0);
+        while filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => filled += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => {
+                    self.buffer.truncate(filled);
+                    return Err(error);
+                }
+            }
+        }
+        self.buffer.truncate(filled);
+        Ok(())
+    }
+
+    fn found(&mut self, slots: &Slots) -> Option<StreamMatch> {
+        let (start, end) = (slots[0]?, slots[1]?);
+        let found = StreamMatch {
+            start: self.offset + start as u64,
+            end: self.offset + end as u64,
+            bytes: self.buffer[start..end].to_vec(),
+        };
+        // an empty match right where the last one ended is not a new match
+        let repeated = start == end && self.last_end == Some(found.end);
+        self.pos = if start == end { end + 1 } else { end };
+        if repeated {
+            return None;
+        }
+        self.last_end = Some(found.end);
+        Some(found)
+    }
+}
+
+impl<R: Read> Iterator for StreamMatches<'_, R> {
+    type Item = io::Result<StreamMatch>;
+
+    fn next(&mut self) -> Option<io::Result<StreamMatch>> {
+        let kind = if self.regex.config.anchored {
+            SearchKind::Prefix
+        } else {
+            SearchKind::Unanchored
+        };
+
+        while !self.done {
+            if self.pos > self.buffer.len() || (self.pos == self.buffer.len() && !self.eof) {
+                if self.eof {
+                    break;
+                }
+                if let Err(error) = self.refill(self.pos.saturating_sub(1)) {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+                continue;
+            }
+
+            let (program, semantics) = (&self.regex.program, self.regex.config.semantics);
+            let progress = if self.eof {
+                Progress::Done(pikevm::search(
+                    program,
+                    semantics,
+                    &self.buffer[..],
+                    self.pos,
+                    kind,
+                ))
+            } else {
+                pikevm::search_partial(program, semantics, &self.buffer[..], self.pos, kind)
+            };
+
+            match progress {
+                Progress::Done(Some(slots)) => {
+                    if let Some(found) = self.found(&slots) {
+                        return Some(Ok(found));
+                    }
+                }
+                Progress::Done(None) => break,
+                Progress::Pending { matched, earliest } => {
+                    // nothing before `earliest` or the match found so far is needed anymore,
+                    // with leftmost-longest that match can start before all of the threads
+                    let earliest = matched
+                        .as_ref()
+                        .and_then(|slots| slots[0])
+                        .map_or(earliest, |start| start.min(earliest));
+                    // unless the match would get too long for the window
+                    let half = self.window / 2;
+                    if self.buffer.len() - earliest > half {
+                        if let Some(slots) = matched {
+                            if let Some(found) = self.found(&slots) {
+                                return Some(Ok(found));
+                            }
+                            continue;
+                        }
+                        self.pos = self.buffer.len() - half;
+                    } else {
+                        self.pos = self.pos.max(earliest);
+                    }
+                    if let Err(error) = self.refill(self.pos.saturating_sub(1)) {
+                        self.done = true;
+                        return Some(Err(error));
+                    }
+                }
+            }
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+impl StreamMatch {
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn range(&self) -> std::ops::Range<u64> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RegexBuilder, Semantics};
+
+    // hands out the input a few bytes at a time like a pipe
+    struct Trickle<'a> {
+        input: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.input.len());
+            buf[..n].copy_from_slice(&self.input[..n]);
+            self.input = &self.input[n..];
+            Ok(n)
+        }
+    }
+
+    fn ranges(re: &Regex, input: &[u8], window: usize, step: usize) -> Vec<std::ops::Range<u64>> {
+        re.stream_find_iter(Trickle { input, step })
+            .window(window)
+            .map(|found| found.unwrap().range())
+            .collect()
+    }
+
+    #[test]
+    fn matches_across_the_window() {
+        let input = "ab 12 cd 345 ef 6 ".repeat(50);
+        for pattern in ["[0-9]+", "[a-z]+ [0-9]+", "x*", "(1|12|cd 3)", "[0-9]+ $"] {
+            let re = Regex::new(pattern).unwrap();
+            let expected: Vec<_> = re
+                .find_iter(input.as_bytes())
+                .map(|m| m.start() as u64..m.end() as u64)
+                .collect();
+            for (window, step) in [(16, 1), (16, 7), (40, 3), (1024, 1024)] {
+                assert_eq!(
+                    ranges(&re, input.as_bytes(), window, step),
+                    expected,
+                    "{pattern} {window} {step}"
+                );
+            }
+        }
+
+        // the match found so far can start before the threads that are still going
+        let re = RegexBuilder::new("(a|bcd)")
+            .semantics(Semantics::LeftmostLongest)
+            .build_bytes()
+            .unwrap();
+        let found = ranges(&re, b"xxxxxxxxxxxxxxabcx", 16, 16);
+        assert_eq!((found.len(), found.first()), (1, Some(&(14..15))));
+        for pattern in ["(1|12|cd 3)", "(b 1|b|12 cd|2)", "[0-9]+ $"] {
+            let re = RegexBuilder::new(pattern)
+                .semantics(Semantics::LeftmostLongest)
+                .build_bytes()
+                .unwrap();
+            let expected: Vec<_> = re
+                .find_iter(input.as_bytes())
+                .map(|m| m.start() as u64..m.end() as u64)
+                .collect();
+            for (window, step) in [(16, 1), (16, 7), (40, 3)] {
+                assert_eq!(
+                    ranges(&re, input.as_bytes(), window, step),
+                    expected,
+                    "{pattern} {window} {step}"
+                );
+            }
+        }
+
+        // the assertions see past the end of a buffer
+        let re = RegexBuilder::new("^[a-z]+$")
+            .multi_line(true)
+            .build_bytes()
+            .unwrap();
+        let input = b"abc\nde1\nfghij\n";
+        assert_eq!(ranges(&re, input, 16, 1), [0..3, 8..13]);
+        let found = re.stream_find_iter(&input[..]).nth(1).unwrap().unwrap();
+        assert_eq!(found.as_bytes(), b"fghij");
+    }
+
+    #[test]
+    fn matches_longer_than_the_window() {
+        // cut into pieces of the window once a match was found
+        let re = Regex::new("a+").unwrap();
+        let input = [b'a'; 100];
+        let found = ranges(&re, &input, 16, 5);
+        assert_eq!(found.first(), Some(&(0..16)));
+        assert_eq!(found.last().map(|m| m.end), Some(100));
+        assert!(found.windows(2).all(|w| w[0].end == w[1].start));
+
+        // given up without one, the later match is still found
+        let re = Regex::new("(x[a-z]*y|z)").unwrap();
+        let mut input = b"x".to_vec();
+        input.extend([b'a'; 50]);
+        input.extend(b"y z");
+        let found = ranges(&re, &input, 16, 4);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0], 53..54);
+        assert_eq!(ranges(&re, &input, 128, 4), [0..52, 53..54]);
+    }
+
+    #[test]
+    fn read_errors() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken pipe"))
+            }
+        }
+        let re = Regex::new("a").unwrap();
+        let mut matches = re.stream_find_iter(Broken);
+        assert!(matches.next().unwrap().is_err());
+        assert!(matches.next().is_none());
+    }
+}
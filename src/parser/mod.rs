@@ -1,11 +1,13 @@
 // Scanner will try to make ast of the regex passed after validating it.
 
+use crate::class::CharClass;
+use crate::unicode::Property;
+
 pub(crate) type Expression = (Anchor, Vec<Pattern>);
 
 //TODO: need to to add other context, this is not helpful in current state
-#[derive(Debug)]
-pub(crate) enum ParsingError {
-    NotAsciiCharacter,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsingError {
     MisusedAnchorChracter,
     NotTerminatedProperly,
     UnknownGuardCharacter,
@@ -13,6 +15,22 @@ pub(crate) enum ParsingError {
     UnknownPredefinedSetName,
     NotANumber,
     IncorrectRepetitionLimits,
+    UnknownFlag,
+    InvalidEscapeSequence,
+    InvalidCodePoint,
+    UnknownPropertyName,
+    RepetitionTooLarge,
+    EmptyAlternative,
+    NestTooDeep,
+    InvalidGroupName,
+    NonAsciiInByteClass,
+}
+
+// Where in the pattern the error was found, as a byte offset into the original pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ParsingError,
+    pub position: usize,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -25,21 +43,33 @@ pub(crate) struct Pattern {
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) enum SubPattern {
     Dot,
+    // a dot under the `s` flag, it matches the line terminator too
+    AnyChar,
     Char(char),
     // InvertedChar(char), //TODO: check if this is correct according to spec
     BracketedSet(Vec<Sets>),
     InvertedSet(Vec<Sets>),
     Alternative(Vec<Vec<Pattern>>),
+    // a capturing group that can be looked up by its name as well
+    NamedGroup(String, Vec<Vec<Pattern>>),
+    // a quantifier on an already quantified atom, `a{2}{3}` is `a{2}` repeated three times
+    Repeated(Box<Pattern>),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum Sets {
     PredefinedSets(PredefinedSet),
     CustomRange(Range),
     Custom(Vec<char>),
+    // \p{..} and \P{..}
+    Property(Property),
+    NegatedProperty(Property),
+    // what is left after nested classes and set operations are evaluated
+    Class(CharClass),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum PredefinedSet {
     AlNum, // that is the name used in `info grep`
     Alpha,
@@ -53,10 +83,11 @@ pub(crate) enum PredefinedSet {
     Punct,  // Puntuation
     Space,  // TODO: how is that different from the blank
     XDigit, // Hexa Decimal
+    Word,   // [:alnum:] and '_', a GNU extension and what \w is
 }
 
 // The custom range will be like this [0-5] [4-9]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Range(pub(crate) char, pub(crate) char);
 
 #[derive(Debug, PartialEq, Eq)]
@@ -79,31 +110,177 @@ pub(crate) enum Repetition {
     None,
 }
 
-// impl Display for ParsingError {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         writeln!(
-//             f,
-//             {
-//                 use ParsingError::*;
-//                 match self {
-//                     NotAsciiCharacter,
-//                     MisusedAnchorChracter,
-//                     NotTerminatedProperly,
-//                     UnknownGuardCharacter,
-//                     MalformedExpression,
-//                     UnknownPredefinedSetName,
-//                     NotANumber,
-//                     IncorrectRepetitionLimits,
-//                 }
-//             }
-//         )
-//     }
-// }
+// The flavour of the syntax, ERE is what `grep -E` understands and BRE is plain `grep` and `sed`.
+// Perl is ERE with the `\d`, `\w` and `\s` classes and their negations.
+// BRE doesn't have the GNU extensions for alternation outside of a group or anchors inside of
+// one, `a\|b`, `\(^a\)` and `\(a$\)` are errors like they are in ERE.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Basic,
+    #[default]
+    Extended,
+    Perl,
+}
+
+// the largest count allowed in an interval unless it is configured, the same as glibc
+pub const RE_DUP_MAX: usize = 0x7fff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Options {
+    pub(crate) syntax: Syntax,
+    pub(crate) dot_matches_newline: bool,
+    // nested brackets and the `&&`, `--` and `~~` operators of UTS #18 inside of brackets
+    pub(crate) set_operations: bool,
+    // brackets exactly like POSIX, a backslash inside of them is not an escape
+    pub(crate) posix_brackets: bool,
+    // the `x` flag, whitespace and comments from '#' till the end of the line are skipped
+    pub(crate) extended: bool,
+    // a '{' that doesn't start a valid interval is an error instead of a literal like in GNU
+    pub(crate) strict_intervals: bool,
+    pub(crate) dup_max: usize,
+    // `a**` and `a{2}{3}` nest the repetitions instead of being an error
+    pub(crate) stacked_repetitions: bool,
+    // `()`, `(|a)` and `(a|)` match the empty string, POSIX leaves them undefined
+    pub(crate) empty_alternatives: bool,
+    // the `i` flag
+    pub(crate) case_insensitive: bool,
+    // POSIX and perl classes and case folding go beyond ASCII
+    pub(crate) unicode: bool,
+    // how many more groups, nested brackets or stacked repetitions can be opened
+    pub(crate) nest_limit: usize,
+    // the pattern is for a `bytes::Regex`, without unicode its classes match single bytes
+    pub(crate) bytes: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            syntax: Syntax::default(),
+            dot_matches_newline: false,
+            set_operations: false,
+            posix_brackets: false,
+            extended: false,
+            strict_intervals: false,
+            dup_max: RE_DUP_MAX,
+            stacked_repetitions: false,
+            empty_alternatives: true,
+            case_insensitive: false,
+            unicode: false,
+            nest_limit: 250,
+            bytes: false,
+        }
+    }
+}
+
+// These are the POSIX classes in the C locale, so only ASCII is considered
+impl PredefinedSet {
+    pub(crate) fn contains(&self, c: char) -> bool {
+        match self {
+            PredefinedSet::AlNum => c.is_ascii_alphanumeric(),
+            PredefinedSet::Alpha => c.is_ascii_alphabetic(),
+            PredefinedSet::Blank => c == ' ' || c == '\t',
+            PredefinedSet::Digit => c.is_ascii_digit(),
+            PredefinedSet::Graph => c.is_ascii_graphic(),
+            PredefinedSet::Lower => c.is_ascii_lowercase(),
+            PredefinedSet::Upper => c.is_ascii_uppercase(),
+            PredefinedSet::Print => c.is_ascii_graphic() || c == ' ',
+            PredefinedSet::Punct => c.is_ascii_punctuation(),
+            // unlike `is_ascii_whitespace` this includes the vertical tab
+            PredefinedSet::Space => c.is_ascii_whitespace() || c == '\x0b',
+            PredefinedSet::XDigit => c.is_ascii_hexdigit(),
+            PredefinedSet::Word => c.is_ascii_alphanumeric() || c == '_',
+        }
+    }
+}
+
+impl std::fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ParsingError::*;
+        let message = match self {
+            MisusedAnchorChracter => "anchor character used more than once",
+            NotTerminatedProperly => "expression was not terminated properly",
+            UnknownGuardCharacter => "unknown character after '[' in a bracket expression",
+            MalformedExpression => "malformed expression",
+            UnknownPredefinedSetName => "unknown name of a predefined set",
+            NotANumber => "repetition limit is not a number",
+            IncorrectRepetitionLimits => "incorrect limits",
+            UnknownFlag => "unknown flag in (?...)",
+            InvalidEscapeSequence => "invalid escape sequence",
+            InvalidCodePoint => "escape sequence is not a valid unicode scalar value",
+            UnknownPropertyName => "unknown unicode property",
+            RepetitionTooLarge => "repetition count is over the limit",
+            EmptyAlternative => "empty group or alternative",
+            NestTooDeep => "groups are nested too deeply",
+            InvalidGroupName => "invalid name of a group",
+            NonAsciiInByteClass => "non-ASCII character in a class that matches bytes",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for ParsingError {}
+
+impl Options {
+    // '.', classes and `\xHH` stand for bytes and not for UTF-8 encoded characters
+    pub(crate) fn byte_classes(&self) -> bool {
+        self.bytes && !self.unicode
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.position)
+    }
+}
+
+impl std::error::Error for Error {}
 
 mod parse;
 
-pub(crate) fn process(line: &'_ str) -> Result<Expression, ParsingError> {
-    parse::process(line)
+// Escapes every character that means something in a pattern, `process` turns the result
+// back into exactly the characters of `text`. Whitespace is escaped too so it isn't skipped
+// in extended mode. That is for ERE and Perl, BRE needs `escape_for`.
+pub fn escape(text: &str) -> String {
+    escape_for(text, Syntax::Extended)
+}
+
+// In BRE it is the other way around for some of them, `\+` and `\{` are a repetition there
+// and a plain `+` or `{` is already literal so they are left as they are.
+pub fn escape_for(text: &str, syntax: Syntax) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        let control = match c {
+            '\n' => Some('n'),
+            '\t' => Some('t'),
+            '\r' => Some('r'),
+            '\x0b' => Some('v'),
+            '\x0c' => Some('f'),
+            _ => None,
+        };
+        if let Some(letter) = control {
+            escaped.push('\\');
+            escaped.push(letter);
+            continue;
+        }
+        let special = match c {
+            '\\' | '.' | '*' | '[' | ']' | '^' | '$' | '#' | '&' | '-' | '~' => true,
+            '+' | '?' | '(' | ')' | '|' | '{' | '}' => syntax != Syntax::Basic,
+            c => c.is_whitespace(),
+        };
+        if special {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub(crate) fn process(line: &'_ str) -> Result<Expression, Error> {
+    parse::process_with(line, &Options::default())
+}
+
+pub(crate) fn process_with(line: &'_ str, options: &Options) -> Result<Expression, Error> {
+    parse::process_with(line, options)
 }
 
 #[cfg(test)]
@@ -250,4 +427,57 @@ mod test {
         );
         assert_eq!(process(exp).unwrap(), ans);
     }
+
+    #[test]
+    fn escaping_round_trips() {
+        let literal = |text: &str| {
+            text.chars()
+                .map(|c| Pattern {
+                    sub_pattern: SubPattern::Char(c),
+                    repetition: Repetition::None,
+                })
+                .collect()
+        };
+        for text in [
+            "",
+            r"\.+*?()|[]{}^$#&-~",
+            "a{2}b",
+            r"C:\Program Files\x41\n",
+            "café $5.00 (tax)",
+            "^[[:alpha:]]*$",
+            r"\Q\E",
+        ] {
+            assert_eq!(
+                process(&escape(text)).unwrap(),
+                (Anchor::None, literal(text))
+            );
+        }
+
+        // whitespace isn't skipped in extended mode
+        let extended = Options {
+            extended: true,
+            ..Options::default()
+        };
+        for text in ["a b", "tab\there\r\n", "\u{a0}\u{2003}\x0b\x0c#"] {
+            assert_eq!(
+                process_with(&escape(text), &extended).unwrap(),
+                (Anchor::None, literal(text)),
+                "{text:?}"
+            );
+        }
+
+        // BRE has its own operators
+        let basic = Options {
+            syntax: Syntax::Basic,
+            ..Options::default()
+        };
+        for text in [r"\.+*?()|[]{}^$#&-~", "a+", "a{2}b", "*a\\(b\\)$"] {
+            assert_eq!(
+                process_with(&escape_for(text, Syntax::Basic), &basic).unwrap(),
+                (Anchor::None, literal(text)),
+                "{text:?}"
+            );
+        }
+        assert_eq!(escape_for("a+{2}", Syntax::Basic), "a+{2}");
+    }
 }
@@ -2,91 +2,258 @@ use std::fmt::Debug;
 use std::iter::Peekable;
 use std::str::Chars;
 
+use crate::class::CharClass;
+use crate::unicode;
+
 use super::{
-    Anchor, Expression, ParsingError, Pattern, PredefinedSet, Range, Repetition, Sets, SubPattern,
+    Anchor, Error, Expression, Options, ParsingError, Pattern, PredefinedSet, Range, Repetition,
+    Sets, SubPattern, Syntax,
 };
 
-const NORMAL_CHAR: [char; 80] = [
-    ' ', '!', '"', '#', '%', '&', '\'', ',', '-', '/', '0', '1', '2', '3', '4', '5', '6', '7', '8',
-    '9', ':', ';', '<', '=', '>', '@', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L',
-    'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '_', '`', 'a', 'b', 'c',
-    'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v',
-    'w', 'x', 'y', 'z',
-];
+// Outside of brackets these are never taken literally without a backslash (in ERE)
+const META_CHAR: [char; 8] = ['(', ')', '*', '+', '?', '[', '{', '|'];
 
-pub(super) fn process(line: &'_ str) -> Result<Expression, ParsingError> {
+pub(super) fn process_with(line: &'_ str, options: &Options) -> Result<Expression, Error> {
     let mut iter = line.chars().peekable();
-    let mut expression: Expression = (Anchor::None, Vec::new());
-
     let mut anchor: Anchor = Anchor::None;
-    let mut escaped: bool = false;
+    let mut options = *options;
+
+    match process_sequence(&mut iter, &mut options, Some(&mut anchor)) {
+        Ok((patterns, _)) => Ok((anchor, patterns)),
+        // nothing is ever removed from the pattern before parsing so whatever is left in
+        // the iterator tells where the parser stopped
+        Err(kind) => {
+            let rest: usize = iter.map(char::len_utf8).sum();
+            Err(Error {
+                kind,
+                position: line.len() - rest,
+            })
+        }
+    }
+}
+
+// What made `process_sequence` stop, a group needs to know if another branch follows
+#[derive(Debug, PartialEq, Eq)]
+enum Terminator {
+    EndOfInput,
+    Alternation,
+    GroupEnd,
+}
+
+// Anchors are only allowed on the top level sequence, so `anchor` being None means
+// that we are inside of a group. Flags like `(?s)` change the options till the group ends.
+fn process_sequence(
+    iter: &mut Peekable<Chars<'_>>,
+    options: &mut Options,
+    mut anchor: Option<&mut Anchor>,
+) -> Result<(Vec<Pattern>, Terminator), ParsingError> {
+    let in_group = anchor.is_none();
+    let basic = options.syntax == Syntax::Basic;
+    let mut patterns: Vec<Pattern> = Vec::new();
 
-    // We could change this check for '$' and  maybe
     while let Some(ch) = iter.next() {
-        let pattern: Pattern;
+        // in BRE a '*' with nothing before it to repeat is just a '*'
+        let at_start = patterns.is_empty();
 
-        if escaped {
-            escaped = false;
-            pattern = Pattern {
-                sub_pattern: SubPattern::Char(ch),
-                repetition: check_repetition(&mut iter)?,
-            };
-        } else {
-            pattern = match ch {
-                '\\' => {
-                    escaped = true;
-                    continue;
+        if options.extended && (ch.is_whitespace() || ch == '#') {
+            if ch == '#' {
+                skip_comment(iter);
+            }
+            continue;
+        }
+
+        let sub_pattern = match ch {
+            '\\' => match (iter.next(), basic) {
+                (Some('('), true) => SubPattern::Alternative(check_alternation(iter, options)?),
+                (Some('|'), true) if in_group => return Ok((patterns, Terminator::Alternation)),
+                (Some(')'), true) if in_group => return Ok((patterns, Terminator::GroupEnd)),
+                (Some('|' | ')' | '{' | '}'), true) => {
+                    return Err(ParsingError::MalformedExpression)
                 }
-                sym @ ('^' | '$') => {
-                    use Anchor::*;
-                    match (sym, anchor) {
-                        ('^', None) => {
-                            anchor = Start;
-                            continue;
-                        }
-                        ('^', Start) | ('^', End) | ('^', Both) => {
-                            return Err(ParsingError::MisusedAnchorChracter)
-                        }
-                        ('$', None) => {
-                            anchor = End;
-                            continue;
-                        }
-                        ('$', Start) => {
-                            anchor = Both;
-                            continue;
-                        }
-                        ('$', End) | ('$', Both) => {
-                            return Err(ParsingError::MisusedAnchorChracter)
-                        }
-                        _ => unreachable!(),
+                (Some(kind @ ('p' | 'P')), _) => {
+                    SubPattern::BracketedSet(vec![check_property(kind, iter, options)?])
+                }
+                (Some(kind @ ('d' | 'w' | 's' | 'D' | 'W' | 'S')), _)
+                    if options.syntax == Syntax::Perl =>
+                {
+                    SubPattern::BracketedSet(vec![perl_class(kind, options)])
+                }
+                // everything till \E is literal, a repetition after it only takes the last
+                // character like in perl
+                (Some('Q'), _) => {
+                    let mut quoted = check_quoted(iter);
+                    let Some(last) = quoted.pop() else {
+                        continue;
+                    };
+                    patterns.extend(quoted.into_iter().map(|c| Pattern {
+                        sub_pattern: fold_case(SubPattern::Char(c), options),
+                        repetition: Repetition::None,
+                    }));
+                    SubPattern::Char(last)
+                }
+                (Some(escaped), _) => {
+                    let c = check_escape(escaped, iter)?;
+                    if escaped == 'x' && is_byte(c, options) {
+                        // a set of just the char is what a byte looks like in the ast
+                        SubPattern::BracketedSet(vec![Sets::Custom(vec![c])])
+                    } else {
+                        SubPattern::Char(c)
                     }
                 }
-                '.' => Pattern {
-                    sub_pattern: SubPattern::Dot,
-                    repetition: check_repetition(&mut iter)?,
-                },
-                '[' => Pattern {
-                    sub_pattern: scan_bracketed_expression(&mut iter)?,
-                    repetition: check_repetition(&mut iter)?,
-                },
-                '(' => Pattern {
-                    sub_pattern: check_alternation(&mut iter)?,
-                    repetition: check_repetition(&mut iter)?,
-                },
-                x if NORMAL_CHAR.binary_search(&x).is_ok() => Pattern {
-                    sub_pattern: SubPattern::Char(ch),
-                    repetition: check_repetition(&mut iter)?,
-                },
-                _ => {
-                    dbg!("Lets see what is that: {:#?}", (ch, &iter));
-                    return Err(ParsingError::NotAsciiCharacter);
+                (None, _) => return Err(ParsingError::MalformedExpression),
+            },
+            sym @ ('^' | '$') if !basic => {
+                let Some(anchor) = anchor.as_deref_mut() else {
+                    return Err(ParsingError::MisusedAnchorChracter);
+                };
+                use Anchor::*;
+                match (sym, &anchor) {
+                    ('^', None) => {
+                        *anchor = Start;
+                        continue;
+                    }
+                    ('^', Start) | ('^', End) | ('^', Both) => {
+                        return Err(ParsingError::MisusedAnchorChracter)
+                    }
+                    ('$', None) => {
+                        *anchor = End;
+                        continue;
+                    }
+                    ('$', Start) => {
+                        *anchor = Both;
+                        continue;
+                    }
+                    ('$', End) | ('$', Both) => return Err(ParsingError::MisusedAnchorChracter),
+                    _ => unreachable!(),
                 }
+            }
+            // in BRE '^' is an anchor only at the very beginning and '$' only at the very
+            // end, anywhere else they stand for themselves
+            '^' if basic && at_start && anchor.as_deref() == Some(&Anchor::None) => {
+                if let Some(anchor) = anchor.as_deref_mut() {
+                    *anchor = Anchor::Start;
+                }
+                continue;
+            }
+            '$' if basic && iter.peek().is_none() && !in_group => {
+                if let Some(anchor) = anchor.as_deref_mut() {
+                    *anchor = match anchor {
+                        Anchor::Start => Anchor::Both,
+                        _ => Anchor::End,
+                    };
+                }
+                continue;
+            }
+            '.' if options.dot_matches_newline => SubPattern::AnyChar,
+            '.' => SubPattern::Dot,
+            '[' => scan_bracketed_expression(iter, options)?,
+            '(' if !basic && look_for('?', iter)? => match check_group_name(iter)? {
+                Some(name) => SubPattern::NamedGroup(name, check_alternation(iter, options)?),
+                None => {
+                    check_flags(iter, options)?;
+                    continue;
+                }
+            },
+            '(' if !basic => SubPattern::Alternative(check_alternation(iter, options)?),
+            '|' if !basic && in_group => return Ok((patterns, Terminator::Alternation)),
+            ')' if !basic && in_group => return Ok((patterns, Terminator::GroupEnd)),
+            '*' if basic && at_start => SubPattern::Char(ch),
+            // GNU takes these as anchors of the group, there are none inside of groups here
+            // so they are rejected instead of silently matching the character
+            '^' if basic && in_group && at_start => {
+                return Err(ParsingError::MisusedAnchorChracter)
+            }
+            '$' if basic && in_group && ends_branch(iter) => {
+                return Err(ParsingError::MisusedAnchorChracter)
+            }
+            '^' | '$' | '(' | ')' | '|' | '+' | '?' | '{' | '}' if basic => SubPattern::Char(ch),
+            // check_repetition didn't take it so this can't be an interval, with nothing
+            // to repeat it never is one
+            '{' if !options.strict_intervals => SubPattern::Char(ch),
+            x if !META_CHAR.contains(&x) => SubPattern::Char(ch),
+            _ => return Err(ParsingError::MalformedExpression),
+        };
+
+        let mut pattern = Pattern {
+            sub_pattern: fold_case(sub_pattern, options),
+            repetition: check_repetition(iter, options)?,
+        };
+        // without stacked repetitions a second quantifier is an error, even an interval that
+        // would otherwise be taken as literal text
+        if !options.stacked_repetitions
+            && pattern.repetition != Repetition::None
+            && check_repetition(&mut iter.clone(), options)? != Repetition::None
+        {
+            return Err(ParsingError::MalformedExpression);
+        }
+        let mut depth = 0;
+        while options.stacked_repetitions && pattern.repetition != Repetition::None {
+            let repetition = check_repetition(iter, options)?;
+            if repetition == Repetition::None {
+                break;
+            }
+            depth += 1;
+            if depth > options.nest_limit {
+                return Err(ParsingError::NestTooDeep);
+            }
+            pattern = Pattern {
+                sub_pattern: SubPattern::Repeated(Box::new(pattern)),
+                repetition,
             };
         }
-        expression.1.push(pattern);
+        patterns.push(pattern);
+    }
+
+    if in_group {
+        Err(ParsingError::NotTerminatedProperly)
+    } else {
+        Ok((patterns, Terminator::EndOfInput))
+    }
+}
+
+// in BRE a branch of a group ends at `\|` or `\)`
+fn ends_branch(iter: &Peekable<Chars<'_>>) -> bool {
+    let mut rest = iter.clone();
+    rest.next() == Some('\\') && matches!(rest.next(), Some('|' | ')'))
+}
+
+// \Q is consumed, a missing \E means the rest of the pattern is quoted
+fn check_quoted(iter: &mut Peekable<Chars<'_>>) -> Vec<char> {
+    let mut quoted = Vec::new();
+
+    while let Some(c) = iter.next() {
+        if c == '\\' && iter.peek() == Some(&'E') {
+            let _ = iter.next();
+            break;
+        }
+        quoted.push(c);
+    }
+    quoted
+}
+
+// a '#' comment in extended mode runs till the end of the line
+fn skip_comment<I: Iterator<Item = char>>(iter: &mut Peekable<I>) {
+    for c in iter.by_ref() {
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+// whitespace and comments between an atom and its repetition don't count in extended mode
+fn skip_ignored<I: Iterator<Item = char>>(iter: &mut Peekable<I>, options: &Options) {
+    if !options.extended {
+        return;
+    }
+    loop {
+        match iter.peek() {
+            Some(c) if c.is_whitespace() => {
+                let _ = iter.next();
+            }
+            Some('#') => skip_comment(iter),
+            _ => break,
+        }
     }
-    expression.0 = anchor;
-    Ok(expression)
 }
 
 // this function is impure in one branch only
@@ -107,167 +274,186 @@ fn look_for<I: Iterator<Item = char> + Debug>(
     }
 }
 
-fn check_alternation(iter: &mut Peekable<Chars<'_>>) -> Result<SubPattern, ParsingError> {
-    // make sequence out of iter till the next
+fn check_alternation(
+    iter: &mut Peekable<Chars<'_>>,
+    options: &Options,
+) -> Result<Vec<Vec<Pattern>>, ParsingError> {
+    // every branch is parsed till the next '|' or the closing ')', in BRE both are escaped
+    let mut alternates: Vec<Vec<Pattern>> = Vec::new();
+    let mut options = nested(options)?;
+
+    loop {
+        let (alternate, terminator) = process_sequence(iter, &mut options, None)?;
+        if alternate.is_empty() && !options.empty_alternatives {
+            return Err(ParsingError::EmptyAlternative);
+        }
+        alternates.push(alternate);
+        match terminator {
+            Terminator::Alternation => continue,
+            Terminator::GroupEnd => break,
+            Terminator::EndOfInput => unreachable!("groups can't end without ')'"),
+        }
+    }
 
-    let mut alternates: Vec<Vec<char>> = Vec::new();
-    alternates.push(Vec::new());
+    Ok(alternates)
+}
 
-    for ch in iter.by_ref() {
-        match ch {
-            '|' => {
-                // create a new entry
-                alternates.push(Vec::new());
-            }
-            ')' => {
-                // mark done
-                break;
-            }
-            x => {
-                // collect the char
-                if let Some(last) = alternates.last_mut() {
-                    last.push(x);
-                } else {
-                    panic!("The alternates were empty");
-                }
-            }
+// `(?P<name>` or `(?<name>` with the '(?' already consumed, None when it is not a name but
+// flags. A name is made of letters, digits and '_' and doesn't start with a digit.
+fn check_group_name(iter: &mut Peekable<Chars<'_>>) -> Result<Option<String>, ParsingError> {
+    if look_for('P', iter)? {
+        if !look_for('<', iter)? {
+            return Err(ParsingError::InvalidGroupName);
         }
+    } else if !look_for('<', iter)? {
+        return Ok(None);
     }
 
-    Ok(SubPattern::Alternative(process_subset(alternates)?))
+    let mut name = String::new();
+    loop {
+        match iter.next() {
+            Some('>') => break,
+            Some(c) if c.is_alphanumeric() || c == '_' => name.push(c),
+            Some(_) => return Err(ParsingError::InvalidGroupName),
+            None => return Err(ParsingError::NotTerminatedProperly),
+        }
+    }
+    if name.chars().next().is_none_or(|c| c.is_numeric()) {
+        return Err(ParsingError::InvalidGroupName);
+    }
+    Ok(Some(name))
 }
 
-fn process_subset(alternates: Vec<Vec<char>>) -> Result<Vec<Vec<Pattern>>, ParsingError> {
-    let mut parsed_alternatives = Vec::new();
-    let mut escaped: bool = false;
-    // We could change this check for '$' and  maybe
-    let iters = alternates
-        .into_iter()
-        .map(|iter| iter.into_iter().peekable());
-    for mut iter in iters {
-        let mut alternate = Vec::new();
-        while let Some(ch) = iter.next() {
-            let pattern: Pattern;
+// (?s) turns a flag on and (?-s) turns it off, the '(?' is already consumed. (?#...) is
+// a comment and is skipped as a whole.
+fn check_flags(iter: &mut Peekable<Chars<'_>>, options: &mut Options) -> Result<(), ParsingError> {
+    let mut enable = true;
 
-            if escaped {
-                escaped = false;
-                pattern = Pattern {
-                    sub_pattern: SubPattern::Char(ch),
-                    repetition: check_repetition(&mut iter)?,
-                };
-            } else {
-                pattern = match ch {
-                    '\\' => {
-                        escaped = true;
-                        continue;
-                    }
-                    '.' => Pattern {
-                        sub_pattern: SubPattern::Dot,
-                        repetition: check_repetition(&mut iter)?,
-                    },
-                    '[' => Pattern {
-                        sub_pattern: scan_bracketed_expression(&mut iter)?,
-                        repetition: check_repetition(&mut iter)?,
-                    },
-                    x if NORMAL_CHAR.binary_search(&x).is_ok() => Pattern {
-                        sub_pattern: SubPattern::Char(ch),
-                        repetition: check_repetition(&mut iter)?,
-                    },
-                    _ => {
-                        dbg!("Lets see what is that: {:#?}", (ch, &iter));
-                        return Err(ParsingError::NotAsciiCharacter);
-                    }
-                };
-            }
-            alternate.push(pattern);
+    if look_for('#', iter)? {
+        return match iter.by_ref().find(|&c| c == ')') {
+            Some(_) => Ok(()),
+            None => Err(ParsingError::NotTerminatedProperly),
+        };
+    }
+
+    loop {
+        match iter.next() {
+            Some('-') if enable => enable = false,
+            Some('i') => options.case_insensitive = enable,
+            Some('s') => options.dot_matches_newline = enable,
+            Some('x') => options.extended = enable,
+            Some(')') => return Ok(()),
+            Some(_) => return Err(ParsingError::UnknownFlag),
+            None => return Err(ParsingError::NotTerminatedProperly),
         }
-        parsed_alternatives.push(alternate);
     }
-    Ok(parsed_alternatives)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetOperation {
+    Intersection,
+    Difference,
+    SymmetricDifference,
 }
 
 #[inline] // take for example [[:punct:]A-Mm-z ]
-fn scan_bracketed_expression<I: Iterator<Item = char> + Debug>(
+fn scan_bracketed_expression<I: Iterator<Item = char> + Clone + Debug>(
     iter: &mut Peekable<I>,
+    options: &Options,
 ) -> Result<SubPattern, ParsingError> {
     // checking for inverted
     let inverted = look_for('^', iter)?;
 
     let mut sets: Vec<Sets> = Vec::new();
+    // with set operations the left side is evaluated as soon as an operator shows up
+    let mut left: Option<(CharClass, SetOperation)> = None;
+    let mut closed = false;
 
     while iter.peek().is_some() {
-        if look_for('[', iter)? {
+        if let Some(operation) = peek_set_operation(iter, options) {
+            let _ = iter.nth(1);
+            if sets.is_empty() || iter.peek() == Some(&']') {
+                return Err(ParsingError::MalformedExpression);
+            }
+            let right = CharClass::from_sets(&std::mem::take(&mut sets));
+            left = Some((apply_set_operation(left, right), operation));
+            continue;
+        } else if look_for('[', iter)? {
             match iter.peek() {
-                Some(':') => sets.push(get_predefined_set(iter)?),
+                Some(':') => sets.push(get_predefined_set(iter, options)?),
 
-                Some('.') => {
-                    todo!("collating elements");
+                // only single characters are collating elements and in the C locale a
+                // character is the only member of its equivalence class
+                Some(&delimiter @ ('.' | '=')) => {
+                    let element = get_collating_element(delimiter, iter)?;
+                    push_custom(&mut sets, byte_class_char(element, false, options)?);
                 }
 
-                Some('=') => {
-                    todo!("open equivalence class");
+                _ if options.set_operations => {
+                    let class = match scan_bracketed_expression(iter, &nested(options)?)? {
+                        SubPattern::InvertedSet(sets) => CharClass::from_sets(&sets).negate(),
+                        SubPattern::BracketedSet(sets) => CharClass::from_sets(&sets),
+                        _ => unreachable!("brackets are always a set"),
+                    };
+                    sets.push(Sets::Class(class));
                 }
 
-                _ => {
-                    dbg!("This shouldn't end here: {:#?}", &iter);
-                    return Err(ParsingError::UnknownGuardCharacter);
-                }
-            }
-        } else if look_for('\\', iter)? {
-            if let Some(c) = iter.next() {
-                match sets.last_mut() {
-                    Some(Sets::Custom(x)) => {
-                        x.push(c);
-                    }
-                    _ => sets.push(Sets::Custom(vec![c])),
-                }
-            } else {
-                dbg!("invalid escaping: {:#?}", &iter);
-                return Err(ParsingError::MalformedExpression);
-            }
-        } else if look_for('-', iter)? {
-            match sets.last_mut() {
-                Some(Sets::Custom(x)) => {
-                    x.push('-');
-                }
-                _ => sets.push(Sets::Custom(vec!['-'])),
+                _ if options.posix_brackets => push_custom(&mut sets, '['),
+
+                _ => return Err(ParsingError::UnknownGuardCharacter),
             }
+        } else if !options.posix_brackets && look_for('-', iter)? {
+            push_custom(&mut sets, '-');
+        } else if let Some(kind) =
+            peek_perl_class(iter, options).filter(|_| !options.posix_brackets)
+        {
+            let _ = iter.nth(1);
+            sets.push(perl_class(kind, options));
+        } else if let Some(kind) = peek_property(iter).filter(|_| !options.posix_brackets) {
+            let _ = iter.nth(1);
+            sets.push(check_property(kind, iter, options)?);
         } else {
-            // this can be both Custom and Custom Range need to figure out which is which
-            if let Some(maybe_lower) = iter.next() {
-                // this branch check for custom range
-                if look_for('-', iter)? {
-                    let maybe_upper = iter.next().unwrap();
-                    if maybe_lower >= maybe_upper {
+            // this can be both Custom and Custom Range need to figure out which is which,
+            // in POSIX a '-' is a range only when it is not first or last
+            let maybe_lower = scan_bracketed_char(iter, options)?;
+            if peek_set_operation(iter, options).is_some() {
+                // `--` is the difference and not a range
+                push_custom(&mut sets, maybe_lower);
+            } else if look_for('-', iter)? {
+                // a '-' right before the closing ']' is just a '-'
+                if iter.peek() == Some(&']') {
+                    push_custom(&mut sets, maybe_lower);
+                    push_custom(&mut sets, '-');
+                } else {
+                    let maybe_upper = scan_bracketed_char(iter, options)?;
+                    // POSIX allows a range of a single character like [a-a]
+                    if maybe_lower > maybe_upper
+                        || (maybe_lower == maybe_upper && !options.posix_brackets)
+                    {
                         return Err(ParsingError::IncorrectRepetitionLimits);
                     }
                     sets.push(Sets::CustomRange(Range(maybe_lower, maybe_upper)));
-                } else {
-                    // this branch check for custom set
-                    if let Some(a_char) = iter.next_if(|&x| x != ']') {
-                        match sets.last_mut() {
-                            Some(Sets::Custom(last)) => {
-                                last.push(maybe_lower);
-                                last.push(a_char);
-                            }
-                            _ => sets.push(Sets::Custom(vec![maybe_lower, a_char])),
-                        }
-                    } else {
-                        match sets.last_mut() {
-                            Some(Sets::Custom(last)) => {
-                                last.push(maybe_lower);
-                            }
-                            _ => sets.push(Sets::Custom(vec![maybe_lower])),
-                        }
-                    }
                 }
+            } else {
+                push_custom(&mut sets, maybe_lower);
             }
         }
 
         if look_for(']', iter)? {
+            closed = true;
             break;
         }
     }
+    // the pattern can end right after an operator
+    if !closed {
+        return Err(ParsingError::NotTerminatedProperly);
+    }
+
+    // with the option on every bracket expression ends up as one normalized class
+    if options.set_operations {
+        let right = CharClass::from_sets(&sets);
+        sets = vec![Sets::Class(apply_set_operation(left, right))];
+    }
 
     if inverted {
         Ok(SubPattern::InvertedSet(sets))
@@ -276,9 +462,198 @@ fn scan_bracketed_expression<I: Iterator<Item = char> + Debug>(
     }
 }
 
+// the operators are applied from left to right
+fn apply_set_operation(left: Option<(CharClass, SetOperation)>, right: CharClass) -> CharClass {
+    match left {
+        None => right,
+        Some((left, SetOperation::Intersection)) => left.intersect(&right),
+        Some((left, SetOperation::Difference)) => left.difference(&right),
+        Some((left, SetOperation::SymmetricDifference)) => left.symmetric_difference(&right),
+    }
+}
+
+fn peek_set_operation<I: Iterator<Item = char> + Clone + Debug>(
+    iter: &Peekable<I>,
+    options: &Options,
+) -> Option<SetOperation> {
+    if !options.set_operations {
+        return None;
+    }
+    let mut ahead = iter.clone();
+    match (ahead.next(), ahead.next()) {
+        (Some('&'), Some('&')) => Some(SetOperation::Intersection),
+        (Some('-'), Some('-')) => Some(SetOperation::Difference),
+        (Some('~'), Some('~')) => Some(SetOperation::SymmetricDifference),
+        _ => None,
+    }
+}
+
+// a single character inside of brackets, it can be escaped as well unless the brackets
+// are strictly POSIX where a backslash is just a backslash
+fn scan_bracketed_char<I: Iterator<Item = char> + Debug>(
+    iter: &mut Peekable<I>,
+    options: &Options,
+) -> Result<char, ParsingError> {
+    match iter.next() {
+        Some('\\') if !options.posix_brackets => match iter.next() {
+            Some(escaped) => {
+                let c = check_escape(escaped, iter)?;
+                byte_class_char(c, escaped == 'x', options)
+            }
+            None => Err(ParsingError::MalformedExpression),
+        },
+        Some(c) => byte_class_char(c, false, options),
+        None => Err(ParsingError::MalformedExpression),
+    }
+}
+
+// `\xHH` above ASCII when the classes match bytes, the char with that value is the byte
+fn is_byte(c: char, options: &Options) -> bool {
+    options.byte_classes() && !c.is_ascii() && c <= '\u{ff}'
+}
+
+// when a class matches bytes a non-ASCII character would be ambiguous, is it the char or
+// one of its bytes, so only `\xHH` can go above ASCII
+fn byte_class_char(c: char, byte_escape: bool, options: &Options) -> Result<char, ParsingError> {
+    if !options.byte_classes() || c.is_ascii() || (byte_escape && is_byte(c, options)) {
+        Ok(c)
+    } else {
+        Err(ParsingError::NonAsciiInByteClass)
+    }
+}
+
+// is the next thing a \p or \P
+fn peek_property<I: Iterator<Item = char> + Clone + Debug>(iter: &Peekable<I>) -> Option<char> {
+    let mut ahead = iter.clone();
+    match (ahead.next(), ahead.next()) {
+        (Some('\\'), Some(kind @ ('p' | 'P'))) => Some(kind),
+        _ => None,
+    }
+}
+
+// \pL or \p{Greek} with the 'p' or 'P' already consumed
+fn check_property<I: Iterator<Item = char> + Debug>(
+    kind: char,
+    iter: &mut Peekable<I>,
+    options: &Options,
+) -> Result<Sets, ParsingError> {
+    let name = match iter.next() {
+        Some('{') => {
+            let mut name = String::new();
+            loop {
+                match iter.next() {
+                    Some('}') => break name,
+                    Some(c) => name.push(c),
+                    None => return Err(ParsingError::NotTerminatedProperly),
+                }
+            }
+        }
+        Some(c) if c.is_ascii_alphabetic() => c.to_string(),
+        _ => return Err(ParsingError::InvalidEscapeSequence),
+    };
+
+    let property = unicode::property(&name).ok_or(ParsingError::UnknownPropertyName)?;
+    // the properties are about characters, there are no bytes in them
+    if options.byte_classes() {
+        return Err(ParsingError::NonAsciiInByteClass);
+    }
+    if kind == 'P' {
+        Ok(Sets::NegatedProperty(property))
+    } else {
+        Ok(Sets::Property(property))
+    }
+}
+
+// consecutive characters are kept together in one Custom set
+fn push_custom(sets: &mut Vec<Sets>, c: char) {
+    match sets.last_mut() {
+        Some(Sets::Custom(last)) => last.push(c),
+        _ => sets.push(Sets::Custom(vec![c])),
+    }
+}
+
+// The character after a '\' is taken literally unless it starts one of these sequences:
+// \n \t \r \f \v \e \0 \xHH \x{H..} \u{H..} \cX
+fn check_escape<I: Iterator<Item = char> + Debug>(
+    escaped: char,
+    iter: &mut Peekable<I>,
+) -> Result<char, ParsingError> {
+    match escaped {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        'f' => Ok('\x0c'),
+        'v' => Ok('\x0b'),
+        'e' => Ok('\x1b'),
+        '0' => Ok('\0'),
+        'x' if iter.peek() == Some(&'{') => braced_code_point(iter),
+        'x' => {
+            let mut value = 0;
+            for _ in 0..2 {
+                let digit = iter
+                    .next()
+                    .and_then(|d| d.to_digit(16))
+                    .ok_or(ParsingError::InvalidEscapeSequence)?;
+                value = value * 16 + digit;
+            }
+            char::from_u32(value).ok_or(ParsingError::InvalidCodePoint)
+        }
+        'u' if iter.peek() == Some(&'{') => braced_code_point(iter),
+        'u' => Err(ParsingError::InvalidEscapeSequence),
+        // control characters, \cA is 0x01 and \c[ is escape
+        'c' => match iter.next() {
+            Some(c @ ('@'..='_' | 'a'..='z')) => {
+                Ok(char::from(c.to_ascii_uppercase() as u8 ^ 0x40))
+            }
+            _ => Err(ParsingError::InvalidEscapeSequence),
+        },
+        // letters and digits are kept for escapes that mean something, like back references
+        // or `\d` outside of perl syntax, so they don't quietly match themselves
+        x if x.is_ascii_alphanumeric() => Err(ParsingError::InvalidEscapeSequence),
+        x => Ok(x),
+    }
+}
+
+// {H..} with one to six hex digits that make up a scalar value
+fn braced_code_point<I: Iterator<Item = char> + Debug>(
+    iter: &mut Peekable<I>,
+) -> Result<char, ParsingError> {
+    let _ = iter.next();
+    let mut digits = String::with_capacity(6);
+
+    loop {
+        match iter.next() {
+            Some('}') => break,
+            Some(d) if d.is_ascii_hexdigit() && digits.len() < 6 => digits.push(d),
+            Some(_) => return Err(ParsingError::InvalidEscapeSequence),
+            None => return Err(ParsingError::NotTerminatedProperly),
+        }
+    }
+
+    let value =
+        u32::from_str_radix(&digits, 16).map_err(|_| ParsingError::InvalidEscapeSequence)?;
+    char::from_u32(value).ok_or(ParsingError::InvalidCodePoint)
+}
+
+// [.x.] or [=x=] with the '[' already consumed
+fn get_collating_element<I: Iterator<Item = char> + Debug>(
+    delimiter: char,
+    iter: &mut Peekable<I>,
+) -> Result<char, ParsingError> {
+    let _ = iter.next();
+    let element = iter.next().ok_or(ParsingError::NotTerminatedProperly)?;
+
+    if look_for(delimiter, iter)? && look_for(']', iter)? {
+        Ok(element)
+    } else {
+        Err(ParsingError::NotTerminatedProperly)
+    }
+}
+
 #[inline]
 fn get_predefined_set<I: Iterator<Item = char> + Debug>(
     iter: &mut Peekable<I>,
+    options: &Options,
 ) -> Result<Sets, ParsingError> {
     // consuming ':'
     let _ = iter.next();
@@ -294,16 +669,90 @@ fn get_predefined_set<I: Iterator<Item = char> + Debug>(
     let name_terminated_properly = look_for(':', iter)? && look_for(']', iter)?;
 
     if !name_terminated_properly {
-        dbg!("The expression is not valid check the if the brackets where close properly");
         return Err(ParsingError::NotTerminatedProperly);
     }
 
-    Ok(Sets::PredefinedSets(set))
+    Ok(predefined(set, options))
+}
+
+// in unicode mode the POSIX classes are resolved right away since they are not ASCII
+fn predefined(set: PredefinedSet, options: &Options) -> Sets {
+    if options.unicode {
+        Sets::Class(unicode::posix_class(set))
+    } else {
+        Sets::PredefinedSets(set)
+    }
+}
+
+// \d, \w and \s with the backslash already consumed, the uppercase ones are negated
+fn perl_class(kind: char, options: &Options) -> Sets {
+    let set = match kind.to_ascii_lowercase() {
+        'd' => PredefinedSet::Digit,
+        'w' => PredefinedSet::Word,
+        's' => PredefinedSet::Space,
+        _ => unreachable!("not a perl class"),
+    };
+    if kind.is_ascii_uppercase() {
+        Sets::Class(CharClass::from_sets(&[predefined(set, options)]).negate())
+    } else {
+        predefined(set, options)
+    }
+}
+
+// is the next thing one of \d, \w, \s, \D, \W or \S
+fn peek_perl_class<I: Iterator<Item = char> + Clone + Debug>(
+    iter: &Peekable<I>,
+    options: &Options,
+) -> Option<char> {
+    if options.syntax != Syntax::Perl {
+        return None;
+    }
+    let mut ahead = iter.clone();
+    match (ahead.next(), ahead.next()) {
+        (Some('\\'), Some(kind @ ('d' | 'w' | 's' | 'D' | 'W' | 'S'))) => Some(kind),
+        _ => None,
+    }
+}
+
+// under the `i` flag a character or a set matches all the cases of its characters
+fn fold_case(sub_pattern: SubPattern, options: &Options) -> SubPattern {
+    if !options.case_insensitive {
+        return sub_pattern;
+    }
+    let fold = |sets: &[Sets]| {
+        vec![Sets::Class(
+            CharClass::from_sets(sets).case_fold(options.unicode),
+        )]
+    };
+    match sub_pattern {
+        SubPattern::Char(c) => {
+            let class = CharClass::new([(c, c)]).case_fold(options.unicode);
+            if class.len() == 1 {
+                SubPattern::Char(c)
+            } else {
+                SubPattern::BracketedSet(vec![Sets::Class(class)])
+            }
+        }
+        SubPattern::BracketedSet(sets) => SubPattern::BracketedSet(fold(&sets)),
+        SubPattern::InvertedSet(sets) => SubPattern::InvertedSet(fold(&sets)),
+        sub_pattern => sub_pattern,
+    }
+}
+
+// one more level of nesting, the limit is kept in the copy of the options for the group
+fn nested(options: &Options) -> Result<Options, ParsingError> {
+    let nest_limit = options
+        .nest_limit
+        .checked_sub(1)
+        .ok_or(ParsingError::NestTooDeep)?;
+    Ok(Options {
+        nest_limit,
+        ..*options
+    })
 }
 
 #[inline]
 fn match_name_of_set(name: Vec<char>) -> Result<PredefinedSet, ParsingError> {
-    assert!(name.len() == 6 || name.len() == 5);
     let name = String::from_iter(name);
 
     match name.as_str() {
@@ -316,32 +765,67 @@ fn match_name_of_set(name: Vec<char>) -> Result<PredefinedSet, ParsingError> {
         "print" => Ok(PredefinedSet::Print),
         "punct" => Ok(PredefinedSet::Punct),
         "space" => Ok(PredefinedSet::Space),
+        "upper" => Ok(PredefinedSet::Upper),
         "xdigit" => Ok(PredefinedSet::XDigit),
-        _ => {
-            dbg!("discovered some thing while matching predefined set");
-            Err(ParsingError::UnknownPredefinedSetName)
-        }
+        _ => Err(ParsingError::UnknownPredefinedSetName),
     }
 }
 
 #[inline]
-fn check_repetition<I: Iterator<Item = char> + Debug>(
+fn check_repetition<I: Iterator<Item = char> + Clone + Debug>(
     iter: &mut Peekable<I>,
+    options: &Options,
 ) -> Result<Repetition, ParsingError> {
-    match iter.peek() {
-        Some('+') => {
+    skip_ignored(iter, options);
+
+    match (options.syntax, iter.peek()) {
+        (Syntax::Extended | Syntax::Perl, Some('+')) => {
             let _ = iter.next();
             Ok(Repetition::AtLeastOnce)
         }
-        Some('?') => {
+        (Syntax::Extended | Syntax::Perl, Some('?')) => {
             let _ = iter.next();
             Ok(Repetition::AtMostOnce)
         }
-        Some('*') => {
+        (_, Some('*')) => {
             let _ = iter.next();
             Ok(Repetition::ZeroOrMore)
         }
-        Some('{') => exact_repetitions(iter),
+        // GNU takes a '{' that doesn't start an interval as a literal, it is left in `iter`
+        (Syntax::Extended | Syntax::Perl, Some('{')) => {
+            let mut ahead = iter.clone();
+            match exact_repetitions(&mut ahead, options) {
+                Err(
+                    ParsingError::MalformedExpression
+                    | ParsingError::NotTerminatedProperly
+                    | ParsingError::NotANumber,
+                ) if !options.strict_intervals => Ok(Repetition::None),
+                result => {
+                    *iter = ahead;
+                    result
+                }
+            }
+        }
+        // BRE spells the other repetitions with a backslash (`\+` and `\?` are GNU extensions)
+        (Syntax::Basic, Some('\\')) => {
+            let mut ahead = iter.clone();
+            let _ = ahead.next();
+            match ahead.peek() {
+                Some('{') => {
+                    let _ = iter.next();
+                    exact_repetitions(iter, options)
+                }
+                Some('+') => {
+                    let _ = iter.nth(1);
+                    Ok(Repetition::AtLeastOnce)
+                }
+                Some('?') => {
+                    let _ = iter.nth(1);
+                    Ok(Repetition::AtMostOnce)
+                }
+                _ => Ok(Repetition::None),
+            }
+        }
         _ => Ok(Repetition::None),
     }
 }
@@ -349,83 +833,59 @@ fn check_repetition<I: Iterator<Item = char> + Debug>(
 #[inline]
 fn exact_repetitions<I: Iterator<Item = char> + Debug>(
     iter: &mut Peekable<I>,
+    options: &Options,
 ) -> Result<Repetition, ParsingError> {
     let mut number_string = [String::new(), String::new()];
     let mut current_number = 0;
-    let mut is_exact = true;
+    let mut closed = false;
 
     let _ = iter.next();
-    for n in iter.by_ref() {
+    while let Some(n) = iter.next() {
         match n {
-            ',' => {
-                current_number += 1;
-                is_exact = false;
-            }
-            '}' => {
+            ',' if current_number == 0 => current_number += 1,
+            '}' if options.syntax != Syntax::Basic => {
+                closed = true;
                 break;
             }
-            x if x.is_numeric() => {
-                number_string[current_number].push(x);
-            }
-            _ => {
-                dbg!("Some thing is stuck here: \n {:#?}", n);
-                return Err(ParsingError::MalformedExpression);
+            '\\' if options.syntax == Syntax::Basic && look_for('}', iter)? => {
+                closed = true;
+                break;
             }
+            x if x.is_ascii_digit() => number_string[current_number].push(x),
+            _ => return Err(ParsingError::MalformedExpression),
         }
     }
+    if !closed {
+        return Err(ParsingError::NotTerminatedProperly);
+    }
 
-    if is_exact && current_number == 0 && number_string[1].is_empty() {
-        Ok(Repetition::Exactly(
-            number_string[0]
-                .parse::<usize>()
-                .map_err(|_| ParsingError::NotANumber)?,
-        ))
-    } else if current_number == 1 {
-        match [number_string[0].is_empty(), number_string[1].is_empty()] {
-            [true, false] => Ok(Repetition::AtMost(
-                number_string[1]
-                    .parse::<usize>()
-                    .map_err(|_| ParsingError::NotANumber)?,
-            )),
-
-            [false, false] => Ok(Repetition::InRange(
-                number_string[0]
-                    .parse::<usize>()
-                    .map_err(|_| ParsingError::NotANumber)?,
-                number_string[1]
-                    .parse::<usize>()
-                    .map_err(|_| ParsingError::NotANumber)?,
-            )),
-
-            [false, true] => Ok(Repetition::AtLeast(
-                number_string[0]
-                    .parse::<usize>()
-                    .map_err(|_| ParsingError::NotANumber)?,
-            )),
-
-            _ => {
-                dbg!(
-                    "Found something interesting:\n{}\n {:#?}",
-                    current_number,
-                    number_string
-                );
-                Err(ParsingError::MalformedExpression)
+    // only digits are in there so the parsing can only fail when the number is too big
+    let count = |digits: &str| match digits.parse::<usize>() {
+        Ok(n) if n <= options.dup_max => Ok(n),
+        _ => Err(ParsingError::RepetitionTooLarge),
+    };
+    let [lower, upper] = &number_string;
+    match (current_number, lower.is_empty(), upper.is_empty()) {
+        (0, false, _) => Ok(Repetition::Exactly(count(lower)?)),
+        (1, false, true) => Ok(Repetition::AtLeast(count(lower)?)),
+        (1, true, false) => Ok(Repetition::AtMost(count(upper)?)),
+        (1, false, false) => {
+            let (n, m) = (count(lower)?, count(upper)?);
+            if n > m {
+                return Err(ParsingError::IncorrectRepetitionLimits);
             }
+            Ok(Repetition::InRange(n, m))
         }
-    } else {
-        dbg!(
-            "Found something interesting:\n{}\n {:#?}",
-            current_number,
-            number_string
-        );
-        Err(ParsingError::MalformedExpression)
+        // `{}` and `{,}`
+        _ => Err(ParsingError::NotANumber),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::process;
+    use super::process_with;
     use crate::parser::*;
+    use crate::unicode;
 
     #[test]
     fn test_exact_repetition_1() {
@@ -721,4 +1181,579 @@ mod test {
 
         assert_eq!(process(exp).unwrap(), ans);
     }
+
+    // every char of `text` as a literal without a repetition
+    fn literals(text: &str) -> Vec<Pattern> {
+        text.chars()
+            .map(|c| Pattern {
+                sub_pattern: SubPattern::Char(c),
+                repetition: Repetition::None,
+            })
+            .collect()
+    }
+
+    fn basic(expr: &str) -> Result<Expression, Error> {
+        process_with(
+            expr,
+            &Options {
+                syntax: Syntax::Basic,
+                ..Options::default()
+            },
+        )
+    }
+
+    #[test]
+    fn bre_groups_are_escaped() {
+        let ans = (
+            Anchor::None,
+            vec![Pattern {
+                sub_pattern: SubPattern::Alternative(vec![
+                    vec![Pattern {
+                        sub_pattern: SubPattern::Char('a'),
+                        repetition: Repetition::None,
+                    }],
+                    vec![Pattern {
+                        sub_pattern: SubPattern::Char('b'),
+                        repetition: Repetition::None,
+                    }],
+                ]),
+                repetition: Repetition::ZeroOrMore,
+            }],
+        );
+        assert_eq!(basic(r"\(a\|b\)*").unwrap(), ans);
+        assert_eq!(process(r"(a|b)*").unwrap(), ans);
+    }
+
+    #[test]
+    fn bre_parenthesis_and_bar_are_literal() {
+        let ans = (
+            Anchor::None,
+            vec![
+                Pattern {
+                    sub_pattern: SubPattern::Char('('),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('a'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('|'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char(')'),
+                    repetition: Repetition::None,
+                },
+            ],
+        );
+        assert_eq!(basic(r"(a|)").unwrap(), ans);
+        assert_eq!(process(r"\(a\|\)").unwrap(), ans);
+    }
+
+    #[test]
+    fn bre_intervals_are_escaped() {
+        let ans = (
+            Anchor::None,
+            vec![Pattern {
+                sub_pattern: SubPattern::Char('1'),
+                repetition: Repetition::InRange(2, 25),
+            }],
+        );
+        assert_eq!(basic(r"1\{2,25\}").unwrap(), ans);
+        assert_eq!(process(r"1{2,25}").unwrap(), ans);
+        assert!(basic(r"1\{2,25}").is_err());
+    }
+
+    #[test]
+    fn bre_braces_are_literal() {
+        let ans = (
+            Anchor::None,
+            vec![
+                Pattern {
+                    sub_pattern: SubPattern::Char('1'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('{'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('2'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('}'),
+                    repetition: Repetition::None,
+                },
+            ],
+        );
+        assert_eq!(basic(r"1{2}").unwrap(), ans);
+    }
+
+    #[test]
+    fn bre_plus_and_question_mark() {
+        let escaped = (
+            Anchor::None,
+            vec![
+                Pattern {
+                    sub_pattern: SubPattern::Char('a'),
+                    repetition: Repetition::AtLeastOnce,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('b'),
+                    repetition: Repetition::AtMostOnce,
+                },
+            ],
+        );
+        assert_eq!(basic(r"a\+b\?").unwrap(), escaped);
+        assert_eq!(process(r"a+b?").unwrap(), escaped);
+
+        let literal = (
+            Anchor::None,
+            vec![
+                Pattern {
+                    sub_pattern: SubPattern::Char('a'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('+'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('b'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('?'),
+                    repetition: Repetition::None,
+                },
+            ],
+        );
+        assert_eq!(basic(r"a+b?").unwrap(), literal);
+        assert_eq!(process(r"a\+b\?").unwrap(), literal);
+    }
+
+    #[test]
+    fn bre_leading_star_is_literal() {
+        let star = |repetition| Pattern {
+            sub_pattern: SubPattern::Char('*'),
+            repetition,
+        };
+
+        assert_eq!(
+            basic(r"^**").unwrap(),
+            (Anchor::Start, vec![star(Repetition::ZeroOrMore)])
+        );
+        assert_eq!(
+            basic(r"\(*\)").unwrap(),
+            (
+                Anchor::None,
+                vec![Pattern {
+                    sub_pattern: SubPattern::Alternative(vec![vec![star(Repetition::None)]]),
+                    repetition: Repetition::None,
+                }]
+            )
+        );
+        assert!(process(r"*").is_err());
+    }
+
+    #[test]
+    fn bre_anchors_only_at_the_ends() {
+        let ans = (
+            Anchor::Both,
+            vec![
+                Pattern {
+                    sub_pattern: SubPattern::Char('a'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('^'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('$'),
+                    repetition: Repetition::None,
+                },
+                Pattern {
+                    sub_pattern: SubPattern::Char('b'),
+                    repetition: Repetition::None,
+                },
+            ],
+        );
+        assert_eq!(basic(r"^a^$b$").unwrap(), ans);
+        assert!(process(r"^a^$b$").is_err());
+    }
+
+    #[test]
+    fn bre_gnu_extensions_are_rejected() {
+        // no alternation outside of a group and no anchors inside of one, like in ERE
+        let error = |expr| basic(expr).unwrap_err().kind;
+        assert_eq!(error(r"a\|b"), ParsingError::MalformedExpression);
+        assert_eq!(error(r"\(^a\)"), ParsingError::MisusedAnchorChracter);
+        assert_eq!(error(r"\(a\|^b\)"), ParsingError::MisusedAnchorChracter);
+        assert_eq!(error(r"\(a$\)"), ParsingError::MisusedAnchorChracter);
+        assert_eq!(error(r"\(a$\|b\)"), ParsingError::MisusedAnchorChracter);
+        // anywhere else in a group they are still the characters
+        assert!(basic(r"\(a^b$c\)").is_ok());
+    }
+
+    #[test]
+    fn dot_all_flag() {
+        let dot = |sub_pattern| Pattern {
+            sub_pattern,
+            repetition: Repetition::None,
+        };
+        let ans = (
+            Anchor::None,
+            vec![
+                dot(SubPattern::Dot),
+                dot(SubPattern::AnyChar),
+                dot(SubPattern::Alternative(vec![
+                    vec![dot(SubPattern::Dot)],
+                    vec![dot(SubPattern::Dot)],
+                ])),
+                dot(SubPattern::AnyChar),
+            ],
+        );
+        assert_eq!(process(r".(?s).((?-s).|.).").unwrap(), ans);
+        assert_eq!(
+            process(r"(?q)").unwrap_err().kind,
+            ParsingError::UnknownFlag
+        );
+        assert_eq!(
+            process(r"(?s").unwrap_err().kind,
+            ParsingError::NotTerminatedProperly
+        );
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let chars = |expr| match process(expr).unwrap() {
+            (Anchor::None, patterns) => patterns
+                .into_iter()
+                .map(|p| match p.sub_pattern {
+                    SubPattern::Char(c) => c,
+                    other => panic!("not a char: {other:?}"),
+                })
+                .collect::<String>(),
+            other => panic!("unexpected anchor: {other:?}"),
+        };
+
+        assert_eq!(chars(r"\n\t\r\f\v\e\0"), "\n\t\r\x0c\x0b\x1b\0");
+        assert_eq!(chars(r"\x41\x{42}\u{1F600}\u{e9}"), "AB\u{1F600}\u{e9}");
+        assert_eq!(chars(r"\cA\cz\c["), "\x01\x1a\x1b");
+        // punctuation and other characters that aren't letters or digits are literal
+        assert_eq!(chars(r"\.\/\-\<\é"), "./-<é");
+    }
+
+    #[test]
+    fn escape_sequences_in_brackets() {
+        let ans = (
+            Anchor::None,
+            vec![Pattern {
+                sub_pattern: SubPattern::InvertedSet(vec![
+                    Sets::CustomRange(Range('\0', '\x1f')),
+                    Sets::Custom(vec!['\t', '-', '\u{7f}']),
+                ]),
+                repetition: Repetition::None,
+            }],
+        );
+        assert_eq!(process(r"[^\x00-\x1f\t\-\x{7f}]").unwrap(), ans);
+    }
+
+    #[test]
+    fn invalid_escape_sequences() {
+        use ParsingError::*;
+        for (expr, error) in [
+            (r"\x4", InvalidEscapeSequence),
+            (r"\xg1", InvalidEscapeSequence),
+            (r"\x{}", InvalidEscapeSequence),
+            (r"\x{1234567}", InvalidEscapeSequence),
+            (r"\x{110000}", InvalidCodePoint),
+            (r"\u{d800}", InvalidCodePoint),
+            (r"\u41", InvalidEscapeSequence),
+            (r"\u{41", NotTerminatedProperly),
+            (r"\c1", InvalidEscapeSequence),
+            (r"[\x{zz}]", InvalidEscapeSequence),
+            (r"\q", InvalidEscapeSequence),
+            (r"\b", InvalidEscapeSequence),
+            (r"\d", InvalidEscapeSequence),
+            (r"[\1]", InvalidEscapeSequence),
+        ] {
+            assert_eq!(process(expr).unwrap_err().kind, error, "{expr}");
+        }
+        // back references aren't supported, they don't match the digit instead
+        assert_eq!(basic(r"\(a\)\1").unwrap_err().kind, InvalidEscapeSequence);
+    }
+
+    #[test]
+    fn unicode_and_other_literals() {
+        assert_eq!(
+            process("café~日本").unwrap(),
+            (Anchor::None, literals("café~日本"))
+        );
+        assert_eq!(process("{a}]").unwrap(), (Anchor::None, literals("{a}]")));
+        assert_eq!(
+            process("+a").unwrap_err().kind,
+            ParsingError::MalformedExpression
+        );
+        assert_eq!(
+            process("a|b").unwrap_err().kind,
+            ParsingError::MalformedExpression
+        );
+    }
+
+    #[test]
+    fn unicode_properties() {
+        let set = |sets| Pattern {
+            sub_pattern: SubPattern::BracketedSet(sets),
+            repetition: Repetition::AtLeastOnce,
+        };
+        let greek = unicode::property("Greek").unwrap();
+        let letter = unicode::property("L").unwrap();
+
+        assert_eq!(
+            process(r"\p{Greek}+\PL+").unwrap(),
+            (
+                Anchor::None,
+                vec![
+                    set(vec![Sets::Property(greek)]),
+                    set(vec![Sets::NegatedProperty(letter)]),
+                ]
+            )
+        );
+        assert_eq!(
+            process(r"[\p{Greek}\P{L}0-9]+").unwrap(),
+            (
+                Anchor::None,
+                vec![set(vec![
+                    Sets::Property(greek),
+                    Sets::NegatedProperty(letter),
+                    Sets::CustomRange(Range('0', '9')),
+                ])]
+            )
+        );
+        assert_eq!(
+            process(r"\p{Klingon}").unwrap_err().kind,
+            ParsingError::UnknownPropertyName
+        );
+        assert_eq!(
+            process(r"\p{Greek").unwrap_err().kind,
+            ParsingError::NotTerminatedProperly
+        );
+    }
+
+    #[test]
+    fn bracket_set_operations() {
+        let options = Options {
+            set_operations: true,
+            ..Options::default()
+        };
+        let class = |expr| match process_with(expr, &options)
+            .unwrap()
+            .1
+            .remove(0)
+            .sub_pattern
+        {
+            SubPattern::BracketedSet(mut sets) if sets.len() == 1 => match sets.remove(0) {
+                Sets::Class(class) => class,
+                other => panic!("not evaluated: {other:?}"),
+            },
+            other => panic!("not a single set: {other:?}"),
+        };
+        let chars = |s: &str| CharClass::new(s.chars().map(|c| (c, c)));
+
+        assert_eq!(class(r"[a-f--[aeiou]]"), chars("bcdf"));
+        assert_eq!(class(r"[a-f&&[^b-e]]"), chars("af"));
+        assert_eq!(class(r"[a-c~~b-d]"), chars("ad"));
+        assert_eq!(class(r"[[ab][cd]]"), chars("abcd"));
+        assert_eq!(class(r"[a-z&&[^aeiou]&&a-d]"), chars("bcd"));
+        assert_eq!(class(r"[ab\-\-c--b]"), chars("a-c"));
+
+        for expr in [r"[&&a]", r"[a&&]", r"[a--]"] {
+            assert_eq!(
+                process_with(expr, &options).unwrap_err().kind,
+                ParsingError::MalformedExpression,
+                "{expr}"
+            );
+        }
+        for expr in [r"[a&&", r"[a~~"] {
+            assert_eq!(
+                process_with(expr, &options).unwrap_err().kind,
+                ParsingError::NotTerminatedProperly,
+                "{expr}"
+            );
+        }
+    }
+
+    #[test]
+    fn quoted_literals() {
+        let mut ans = literals("a.*(b|c)[d]");
+        ans.last_mut().unwrap().repetition = Repetition::AtLeastOnce;
+
+        assert_eq!(process(r"\Qa.*(b|c)[d]\E+").unwrap(), (Anchor::None, ans));
+        assert_eq!(
+            process(r"x\Q\E\Q$\").unwrap(),
+            (Anchor::None, literals("x$\\"))
+        );
+    }
+
+    #[test]
+    fn extended_mode_and_comments() {
+        let mut ans = literals("ab c#");
+        ans[1].repetition = Repetition::AtLeastOnce;
+
+        assert_eq!(
+            process("(?x) a b +  # comment\n \\  c \\#").unwrap(),
+            (Anchor::None, ans)
+        );
+        assert_eq!(
+            process("a(?#not [a] pattern)b").unwrap(),
+            (Anchor::None, literals("ab"))
+        );
+        // whitespace is only skipped where (?x) is on and never inside of brackets
+        assert_eq!(process("((?x) a ) [ ]").unwrap().1.len(), 3);
+        assert_eq!(
+            process("a(?#b").unwrap_err().kind,
+            ParsingError::NotTerminatedProperly
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let error = process("(?x) a   b   \\p{Nope} c").unwrap_err();
+        assert_eq!(error.kind, ParsingError::UnknownPropertyName);
+        assert_eq!(error.position, 21);
+        assert_eq!(process("ä(b").unwrap_err().position, 4);
+        assert_eq!(process("ab)c").unwrap_err().position, 3);
+    }
+
+    #[test]
+    fn interval_limits() {
+        let strict = Options {
+            strict_intervals: true,
+            ..Options::default()
+        };
+
+        assert_eq!(process("a{,}").unwrap(), (Anchor::None, literals("a{,}")));
+        assert_eq!(
+            process("a{1,x}").unwrap(),
+            (Anchor::None, literals("a{1,x}"))
+        );
+        for (expr, error) in [
+            ("a{x}", ParsingError::MalformedExpression),
+            ("a{1", ParsingError::NotTerminatedProperly),
+            ("a{}", ParsingError::NotANumber),
+            ("{1}", ParsingError::MalformedExpression),
+        ] {
+            assert_eq!(
+                process_with(expr, &strict).unwrap_err().kind,
+                error,
+                "{expr}"
+            );
+        }
+
+        assert_eq!(
+            process("a{5,2}").unwrap_err().kind,
+            ParsingError::IncorrectRepetitionLimits
+        );
+        assert_eq!(
+            process("a{32768}").unwrap_err().kind,
+            ParsingError::RepetitionTooLarge
+        );
+        assert_eq!(
+            process("a{99999999999999999999999}").unwrap_err().kind,
+            ParsingError::RepetitionTooLarge
+        );
+        let small = Options {
+            dup_max: 3,
+            ..Options::default()
+        };
+        assert!(process_with("a{3}", &small).is_ok());
+        assert!(process_with("a{2,4}", &small).is_err());
+    }
+
+    #[test]
+    fn stacked_repetitions() {
+        let options = Options {
+            stacked_repetitions: true,
+            ..Options::default()
+        };
+        let inner = Pattern {
+            sub_pattern: SubPattern::Char('a'),
+            repetition: Repetition::Exactly(2),
+        };
+        let ans = Pattern {
+            sub_pattern: SubPattern::Repeated(Box::new(Pattern {
+                sub_pattern: SubPattern::Repeated(Box::new(inner)),
+                repetition: Repetition::Exactly(3),
+            })),
+            repetition: Repetition::ZeroOrMore,
+        };
+        assert_eq!(
+            process_with("a{2}{3}*", &options).unwrap(),
+            (Anchor::None, vec![ans])
+        );
+        assert_eq!(
+            process("a+?").unwrap_err().kind,
+            ParsingError::MalformedExpression
+        );
+    }
+
+    #[test]
+    fn empty_alternatives() {
+        let group = |alternates: Vec<Vec<Pattern>>| Pattern {
+            sub_pattern: SubPattern::Alternative(alternates),
+            repetition: Repetition::None,
+        };
+        let a = || Pattern {
+            sub_pattern: SubPattern::Char('a'),
+            repetition: Repetition::None,
+        };
+
+        assert_eq!(process("()").unwrap().1, vec![group(vec![vec![]])]);
+        assert_eq!(
+            process("(|a)").unwrap().1,
+            vec![group(vec![vec![], vec![a()]])]
+        );
+        assert_eq!(
+            process("(a|)").unwrap().1,
+            vec![group(vec![vec![a()], vec![]])]
+        );
+
+        let strict = Options {
+            empty_alternatives: false,
+            ..Options::default()
+        };
+        for expr in ["()", "(|a)", "(a|)", "(a||b)", "x(y|(?s))"] {
+            let error = process_with(expr, &strict).unwrap_err();
+            assert_eq!(error.kind, ParsingError::EmptyAlternative, "{expr}");
+        }
+        assert!(process_with("(a|b)", &strict).is_ok());
+    }
+
+    #[test]
+    fn named_groups() {
+        let a = || Pattern {
+            sub_pattern: SubPattern::Char('a'),
+            repetition: Repetition::None,
+        };
+        let named = |name: &str| Pattern {
+            sub_pattern: SubPattern::NamedGroup(name.to_string(), vec![vec![a()]]),
+            repetition: Repetition::None,
+        };
+        assert_eq!(process("(?P<first>a)").unwrap().1, vec![named("first")]);
+        assert_eq!(process("(?<_2nd>a)").unwrap().1, vec![named("_2nd")]);
+
+        for (expr, error) in [
+            ("(?<>a)", ParsingError::InvalidGroupName),
+            ("(?<1a>a)", ParsingError::InvalidGroupName),
+            ("(?<a-b>a)", ParsingError::InvalidGroupName),
+            ("(?P=a)", ParsingError::InvalidGroupName),
+            ("(?<name", ParsingError::NotTerminatedProperly),
+        ] {
+            assert_eq!(process(expr).unwrap_err().kind, error, "{expr}");
+        }
+    }
 }
@@ -0,0 +1,159 @@
+// Unicode properties for \p{..} and \P{..}, the tables are generated from the Unicode
+// database by scripts/generate-unicode-tables.pl
+
+mod tables;
+
+use crate::class::CharClass;
+use crate::parser::PredefinedSet;
+use tables::{
+    BINARY_PROPERTY, BINARY_PROPERTY_ALIASES, GENERAL_CATEGORY, GENERAL_CATEGORY_ALIASES, SCRIPT,
+    SCRIPT_ALIASES,
+};
+
+type Table = &'static [(&'static str, &'static [(char, char)])];
+type Aliases = &'static [(&'static str, &'static str)];
+
+// A set of code points sorted by their start, the ranges never overlap
+#[derive(Clone, Copy)]
+pub(crate) struct Property {
+    pub(crate) name: &'static str,
+    pub(crate) ranges: &'static [(char, char)],
+}
+
+// the tables are big so only the name is shown
+impl std::fmt::Debug for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Property({})", self.name)
+    }
+}
+
+impl PartialEq for Property {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Property {}
+
+// Accepts `L`, `Greek`, `Emoji` and the explicit forms `gc=Lu`, `sc=Greek` or
+// `Script=Greek`. A bare name is looked for as a category, then a script, then a property.
+pub(crate) fn property(name: &str) -> Option<Property> {
+    match name.split_once(['=', ':']) {
+        Some((kind, value)) => match normalize(kind).as_str() {
+            "gc" | "generalcategory" => lookup(GENERAL_CATEGORY, GENERAL_CATEGORY_ALIASES, value),
+            "sc" | "script" => lookup(SCRIPT, SCRIPT_ALIASES, value),
+            _ => None,
+        },
+        None => match normalize(name).as_str() {
+            "any" => Some(Property {
+                name: "Any",
+                ranges: &[('\0', char::MAX)],
+            }),
+            "ascii" => Some(Property {
+                name: "ASCII",
+                ranges: &[('\0', '\x7f')],
+            }),
+            _ => lookup(GENERAL_CATEGORY, GENERAL_CATEGORY_ALIASES, name)
+                .or_else(|| lookup(SCRIPT, SCRIPT_ALIASES, name))
+                .or_else(|| lookup(BINARY_PROPERTY, BINARY_PROPERTY_ALIASES, name)),
+        },
+    }
+}
+
+// The POSIX classes when they are not just ASCII, these follow annex C of UTS #18
+pub(crate) fn posix_class(set: PredefinedSet) -> CharClass {
+    let class = |name| CharClass::new(property(name).unwrap().ranges.iter().copied());
+    let blank = || class("Zs").union(&CharClass::new([('\t', '\t')]));
+    let graph = || {
+        class("White_Space")
+            .union(&class("Cc"))
+            .union(&class("Cs"))
+            .union(&class("Cn"))
+            .negate()
+    };
+
+    match set {
+        PredefinedSet::AlNum => class("Alphabetic").union(&class("Nd")),
+        PredefinedSet::Alpha => class("Alphabetic"),
+        PredefinedSet::Blank => blank(),
+        PredefinedSet::Digit => class("Nd"),
+        PredefinedSet::Graph => graph(),
+        PredefinedSet::Lower => class("Lowercase"),
+        PredefinedSet::Upper => class("Uppercase"),
+        PredefinedSet::Print => graph().union(&blank()).difference(&class("Cc")),
+        PredefinedSet::Punct => class("P"),
+        PredefinedSet::Space => class("White_Space"),
+        PredefinedSet::XDigit => class("Nd").union(&class("Hex_Digit")),
+        PredefinedSet::Word => class("Alphabetic")
+            .union(&class("M"))
+            .union(&class("Nd"))
+            .union(&class("Pc")),
+    }
+}
+
+fn lookup(table: Table, aliases: Aliases, name: &str) -> Option<Property> {
+    let name = normalize(name);
+    let i = aliases
+        .binary_search_by(|(alias, _)| (*alias).cmp(name.as_str()))
+        .ok()?;
+    let canonical = aliases[i].1;
+    let j = table
+        .binary_search_by(|(name, _)| (*name).cmp(canonical))
+        .ok()?;
+    Some(Property {
+        name: table[j].0,
+        ranges: table[j].1,
+    })
+}
+
+// loose matching from UAX #44, case, spaces, '-' and '_' don't matter
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn looking_up_properties() {
+        assert_eq!(property("Lu").unwrap().name, "Lu");
+        assert_eq!(property("uppercase letter").unwrap().name, "Lu");
+        assert_eq!(property("gc=L").unwrap().name, "L");
+        assert_eq!(property("Grek").unwrap().name, "Greek");
+        assert_eq!(property("Script=Han").unwrap().name, "Han");
+        assert_eq!(property("emoji").unwrap().name, "Emoji");
+        assert!(property("sc=Emoji").is_none());
+        assert!(property("Klingon").is_none());
+    }
+
+    #[test]
+    fn membership() {
+        let property = |name| CharClass::new(property(name).unwrap().ranges.iter().copied());
+
+        let letter = property("L");
+        assert!(letter.contains('a') && letter.contains('é') && letter.contains('日'));
+        assert!(!letter.contains('1') && !letter.contains(' '));
+
+        let greek = property("Greek");
+        assert!(greek.contains('λ') && !greek.contains('l'));
+        assert!(property("Han").contains('本'));
+        assert!(property("Emoji").contains('😀'));
+        assert!(property("Lu").contains('Σ'));
+        assert!(!property("Lu").contains('σ'));
+    }
+
+    #[test]
+    fn unicode_posix_classes() {
+        let alpha = posix_class(PredefinedSet::Alpha);
+        assert!(alpha.contains('é') && alpha.contains('ж') && !alpha.contains('1'));
+        assert!(posix_class(PredefinedSet::Digit).contains('٣'));
+        assert!(posix_class(PredefinedSet::Space).contains('\u{2003}'));
+        let print = posix_class(PredefinedSet::Print);
+        assert!(print.contains(' ') && print.contains('€') && !print.contains('\n'));
+        assert!(posix_class(PredefinedSet::Word).contains('_'));
+    }
+}